@@ -0,0 +1,35 @@
+CREATE table if not exists stream_sessions
+(
+    id                   uuid primary key                     default uuid_generate_v4(),
+    stream_id            VARCHAR(64)                 not null unique,
+    broadcaster_id       bigint                      not null,
+    broadcaster_login    VARCHAR(100)                not null,
+    started_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    ended_at             TIMESTAMP WITHOUT TIME ZONE,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON stream_sessions
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists stream_sessions_open_idx on stream_sessions (broadcaster_id) where ended_at is null;
+
+CREATE table if not exists stream_samples
+(
+    id                   bigserial primary key,
+    session_id           uuid                        not null references stream_sessions (id),
+    viewer_count         integer                     not null,
+    game_id              VARCHAR(64)                 not null,
+    game_name            text                        not null,
+    title                text                        not null,
+    sampled_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE index if not exists stream_samples_session_idx on stream_samples (session_id, sampled_at);
+
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS stream_session_id uuid references stream_sessions (id);
@@ -0,0 +1,5 @@
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS channel_id bigint,
+    ADD COLUMN IF NOT EXISTS channel_login VARCHAR(100);
+
+CREATE index if not exists chat_messages_channel_user_idx on chat_messages (channel_id, user_id, command);
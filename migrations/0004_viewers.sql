@@ -0,0 +1,17 @@
+CREATE table if not exists viewers
+(
+    user_id              bigint primary key,
+    login                VARCHAR(100)                not null,
+    display_name         VARCHAR(100)                not null,
+    fetched_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE unique index if not exists viewers_login_idx on viewers (login);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON viewers
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
@@ -0,0 +1,12 @@
+CREATE table if not exists command_cooldowns
+(
+    scope                VARCHAR(255) primary key,
+    last_used_at         TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE table if not exists rate_limit_buckets
+(
+    name                 VARCHAR(100) primary key,
+    tokens               double precision            not null,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
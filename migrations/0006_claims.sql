@@ -0,0 +1,57 @@
+CREATE table if not exists viewer_wallets
+(
+    user_id              bigint primary key,
+    address              VARCHAR(66)                 not null,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON viewer_wallets
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE table if not exists claim_jobs
+(
+    id                   uuid primary key                     default uuid_generate_v4(),
+    chat_message_id      uuid references chat_messages (id),
+    stream_session_id    uuid references stream_sessions (id),
+    channel_id           bigint                      not null,
+    channel_login        VARCHAR(100)                not null,
+    user_id              bigint                      not null,
+    recipient            VARCHAR(66)                 not null,
+    source               VARCHAR(32)                 not null,
+    status               VARCHAR(32)                 not null default 'pending',
+    attempts             integer                     not null default 0,
+    digest               VARCHAR(64),
+    nft_object_id        VARCHAR(66),
+    effects              jsonb,
+    error                text,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON claim_jobs
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists claim_jobs_status_idx on claim_jobs (status, created_at);
+CREATE index if not exists claim_jobs_channel_user_idx on claim_jobs (channel_id, user_id);
+
+CREATE table if not exists pipeline_state
+(
+    channel_id           bigint primary key,
+    paused               boolean                     not null default false,
+    updated_by           bigint,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON pipeline_state
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
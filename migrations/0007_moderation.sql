@@ -0,0 +1,5 @@
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS twitch_message_id VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS moderated_at TIMESTAMP WITHOUT TIME ZONE;
+
+CREATE index if not exists chat_messages_twitch_message_idx on chat_messages (twitch_message_id);
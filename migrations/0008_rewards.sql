@@ -0,0 +1,5 @@
+ALTER TABLE claim_jobs
+    ADD COLUMN IF NOT EXISTS reward_event VARCHAR(32),
+    ADD COLUMN IF NOT EXISTS nft_name VARCHAR(200),
+    ADD COLUMN IF NOT EXISTS nft_description text,
+    ADD COLUMN IF NOT EXISTS nft_url text;
@@ -0,0 +1,36 @@
+CREATE table if not exists chat_archive
+(
+    id                   uuid                        not null default uuid_generate_v4(),
+    twitch_message_id    VARCHAR(64)                 not null,
+    channel_id           bigint                      not null,
+    channel_login        VARCHAR(100)                not null,
+    sender_id            bigint                      not null,
+    sender_login         VARCHAR(100)                not null,
+    text                 text                        not null,
+    badges               jsonb                       not null default '[]',
+    emotes               jsonb                       not null default '[]',
+    tags                 jsonb                       not null,
+    sent_at              TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    primary key (id, sent_at),
+    unique (twitch_message_id, sent_at)
+) partition by range (sent_at);
+
+CREATE index if not exists chat_archive_channel_idx on chat_archive (channel_id, sent_at);
+CREATE index if not exists chat_archive_sender_idx on chat_archive (sender_id, sent_at);
+
+-- creates the monthly partition that holds `sent_at`, named e.g. chat_archive_2025_06
+CREATE OR REPLACE FUNCTION ensure_chat_archive_partition(sent_at TIMESTAMP WITHOUT TIME ZONE)
+    RETURNS VOID AS
+$$
+DECLARE
+    month_start DATE := date_trunc('month', sent_at);
+BEGIN
+    EXECUTE format(
+            'CREATE TABLE IF NOT EXISTS %I PARTITION OF chat_archive FOR VALUES FROM (%L) TO (%L)',
+            'chat_archive_' || to_char(month_start, 'YYYY_MM'),
+            month_start,
+            month_start + INTERVAL '1 month'
+            );
+END;
+$$ LANGUAGE plpgsql;
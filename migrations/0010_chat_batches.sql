@@ -0,0 +1,31 @@
+CREATE table if not exists chat_batches
+(
+    id                   uuid primary key                     default uuid_generate_v4(),
+    channel_id           bigint                      not null,
+    channel_login        VARCHAR(100)                not null,
+    root                 bytea                       not null,
+    leaf_count           integer                     not null,
+    first_message_at     TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    last_message_at      TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    status               VARCHAR(32)                 not null default 'pending',
+    digest               VARCHAR(64),
+    object_id            VARCHAR(66),
+    error                text,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON chat_batches
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists chat_batches_status_idx on chat_batches (status, created_at);
+
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS batch_id uuid references chat_batches (id),
+    ADD COLUMN IF NOT EXISTS leaf_index integer;
+
+CREATE index if not exists chat_messages_unbatched_idx on chat_messages (channel_id, created_at)
+    where batch_id is null and command = '!STORE';
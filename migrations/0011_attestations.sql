@@ -0,0 +1,9 @@
+CREATE SEQUENCE IF NOT EXISTS attestation_nonces;
+
+ALTER TABLE claim_jobs
+    ADD COLUMN IF NOT EXISTS nonce bigint,
+    ADD COLUMN IF NOT EXISTS attestation bytea,
+    ADD COLUMN IF NOT EXISTS attestation_signature bytea,
+    ADD COLUMN IF NOT EXISTS oracle_public_key bytea;
+
+CREATE UNIQUE index if not exists claim_jobs_nonce_idx on claim_jobs (nonce);
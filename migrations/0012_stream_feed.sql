@@ -0,0 +1,22 @@
+CREATE table if not exists stream_feed_updates
+(
+    id                   uuid primary key                     default uuid_generate_v4(),
+    channel_login        VARCHAR(100)                not null,
+    feed_id              VARCHAR(66)                 not null,
+    live                 boolean                     not null,
+    viewer_count         integer                     not null,
+    game_id              VARCHAR(100)                not null,
+    game_name            text                        not null,
+    digest               VARCHAR(64)                 not null,
+    simulated            boolean                     not null default false,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON stream_feed_updates
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists stream_feed_updates_feed_idx on stream_feed_updates (feed_id, created_at);
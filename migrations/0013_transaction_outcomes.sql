@@ -0,0 +1,27 @@
+CREATE table if not exists transaction_outcomes
+(
+    digest               VARCHAR(64) primary key,
+    success              boolean                     not null,
+    error                text,
+    abort_package        VARCHAR(66),
+    abort_module         VARCHAR(100),
+    abort_code           bigint,
+    computation_cost     bigint                      not null,
+    storage_cost         bigint                      not null,
+    storage_rebate       bigint                      not null,
+    created              jsonb                       not null,
+    mutated              jsonb                       not null,
+    events               jsonb                       not null,
+    simulated            boolean                     not null default false,
+    created_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at           TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON transaction_outcomes
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists transaction_outcomes_failed_idx on transaction_outcomes (created_at)
+    where not success;
@@ -0,0 +1,19 @@
+CREATE table if not exists transaction_journal
+(
+    digest      VARCHAR(64) primary key,
+    transaction bytea                       not null,
+    claim_ids   uuid[]                      not null default '{}',
+    subject     jsonb                       not null,
+    status      VARCHAR(32)                 not null default 'pending',
+    error       text,
+    created_at  TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp,
+    updated_at  TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE OR REPLACE TRIGGER set_timestamp
+    BEFORE UPDATE
+    ON transaction_journal
+    FOR EACH ROW
+EXECUTE FUNCTION update_timestamp();
+
+CREATE index if not exists transaction_journal_status_idx on transaction_journal (status, created_at);
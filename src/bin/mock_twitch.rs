@@ -0,0 +1,25 @@
+//! Offline stand-in for `id.twitch.tv` and `api.twitch.tv`, serving the JSON fixtures in `fixtures/twitch`.
+//!
+//! cargo run --features mock-twitch --bin mock-twitch
+//! TWITCH_AUTH_URL=http://127.0.0.1:8089 TWITCH_API_URL=http://127.0.0.1:8089/helix cargo run
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::info;
+use twitch_sui_oracle::twitch::mock::MockTwitch;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8089";
+const DEFAULT_FIXTURES: &str = "fixtures/twitch";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+    let addr = env::var("MOCK_TWITCH_ADDR").unwrap_or(DEFAULT_ADDR.to_string());
+    let fixtures = env::var("MOCK_TWITCH_FIXTURES").unwrap_or(DEFAULT_FIXTURES.to_string());
+
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Mock Twitch is listening on {}", addr);
+    Arc::new(MockTwitch::new(fixtures)).serve(listener).await?;
+    Ok(())
+}
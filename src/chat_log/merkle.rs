@@ -0,0 +1,180 @@
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Domain separators, so a leaf can never be passed off as an inner node.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// `sha256(0x00 || leaf)`, where `leaf` is the BCS encoding of a chat entry.
+pub fn leaf_hash(leaf: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(leaf);
+    hasher.finalize().into()
+}
+
+/// `sha256(0x01 || left || right)`.
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Binary Merkle tree over leaf hashes. A node without a sibling is promoted
+/// to the next level unchanged instead of being paired with itself.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// `levels[0]` are the leaf hashes, the last level holds only the root.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    /// Returns `None` for an empty batch, which has no root.
+    pub fn new(leaves: Vec<Hash>) -> Option<Self> {
+        if leaves.is_empty() {
+            return None;
+        }
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Some(Self { levels })
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Sibling hashes from the leaf at `index` up to the root, `None` when out of range.
+    /// Levels where the node is promoted have no sibling and add nothing.
+    pub fn proof(&self, index: usize) -> Option<Vec<Hash>> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut index = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            index /= 2;
+        }
+        Some(siblings)
+    }
+}
+
+/// Recomputes the root from a leaf hash and its proof. The position of each
+/// sibling follows from `index` and `leaf_count`, so a proof cannot be moved to another leaf.
+pub fn root_from_proof(
+    leaf: Hash,
+    index: usize,
+    leaf_count: usize,
+    siblings: &[Hash],
+) -> Option<Hash> {
+    if index >= leaf_count {
+        return None;
+    }
+    let mut hash = leaf;
+    let mut index = index;
+    let mut count = leaf_count;
+    let mut siblings = siblings.iter();
+    while count > 1 {
+        if index % 2 == 1 {
+            hash = node_hash(siblings.next()?, &hash);
+        } else if index + 1 < count {
+            hash = node_hash(&hash, siblings.next()?);
+        }
+        index /= 2;
+        count = count.div_ceil(2);
+    }
+    // every sibling has to be used
+    match siblings.next() {
+        Some(_) => None,
+        None => Some(hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<Hash> {
+        (0..count).map(|i| leaf_hash(&[i as u8])).collect()
+    }
+
+    #[test]
+    fn single_leaf_is_the_root() {
+        let leaf = leaf_hash(b"only");
+        let tree = MerkleTree::new(vec![leaf]).unwrap();
+        assert_eq!(tree.root(), leaf);
+        assert_eq!(tree.proof(0), Some(vec![]));
+        assert_eq!(tree.proof(1), None);
+        assert_eq!(root_from_proof(leaf, 0, 1, &[]), Some(leaf));
+        assert!(MerkleTree::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn odd_leaf_counts_promote_the_last_node() {
+        let [a, b, c] = leaves(3).try_into().unwrap();
+        let tree = MerkleTree::new(vec![a, b, c]).unwrap();
+        assert_eq!(tree.root(), node_hash(&node_hash(&a, &b), &c));
+        // `c` has no sibling on the leaf level
+        assert_eq!(tree.proof(2), Some(vec![node_hash(&a, &b)]));
+
+        for count in [3, 5, 7, 9] {
+            let leaves = leaves(count);
+            let tree = MerkleTree::new(leaves.clone()).unwrap();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert_eq!(
+                    root_from_proof(*leaf, index, count, &proof),
+                    Some(tree.root()),
+                    "leaf {} of {}",
+                    index,
+                    count
+                );
+                // the proof does not hold for a neighbour
+                let other = (index + 1) % count;
+                assert_ne!(
+                    root_from_proof(leaves[other], other, count, &proof),
+                    Some(tree.root())
+                );
+            }
+            assert_eq!(tree.proof(count), None);
+        }
+    }
+
+    #[test]
+    fn leaves_and_nodes_are_domain_separated() {
+        let [left, right] = leaves(2).try_into().unwrap();
+        let concatenated = [left, right].concat();
+        assert_ne!(leaf_hash(&concatenated), node_hash(&left, &right));
+
+        let unprefixed: Hash = Sha256::digest(&concatenated).into();
+        assert_ne!(leaf_hash(&concatenated), unprefixed);
+        assert_ne!(node_hash(&left, &right), unprefixed);
+
+        // the inner node of a two-leaf tree does not verify as a one-leaf batch
+        let root = MerkleTree::new(vec![left, right]).unwrap().root();
+        assert_ne!(
+            root_from_proof(leaf_hash(&concatenated), 0, 1, &[]),
+            Some(root)
+        );
+    }
+}
@@ -0,0 +1,217 @@
+pub mod merkle;
+pub mod proof;
+
+use crate::GlobalError;
+use crate::chat_log::merkle::{MerkleTree, leaf_hash};
+use crate::pg::PgClient;
+use deadpool_postgres::Transaction;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// Canonical form of a `!STORE` message, hashed into the batch's Merkle tree
+/// as its BCS encoding. Changing a field changes every root, so keep it stable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatLeaf {
+    pub message_id: [u8; 16],
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub text: String,
+    /// Unix time the message was stored, in milliseconds.
+    pub created_at_ms: u64,
+}
+
+impl ChatLeaf {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        let message_id: Uuid = row.get(0);
+        let channel_id: i64 = row.get(1);
+        let user_id: i64 = row.get(2);
+        let created_at_ms: i64 = row.get(4);
+        Self {
+            message_id: message_id.into_bytes(),
+            channel_id: channel_id as u64,
+            user_id: user_id as u64,
+            text: row.get(3),
+            created_at_ms: created_at_ms as u64,
+        }
+    }
+
+    pub fn to_bcs(&self) -> Result<Vec<u8>, bcs::Error> {
+        bcs::to_bytes(self)
+    }
+
+    pub fn hash(&self) -> Result<merkle::Hash, bcs::Error> {
+        Ok(leaf_hash(&self.to_bcs()?))
+    }
+}
+
+pub(crate) const LEAF_COLUMNS: &str =
+    "id, channel_id, user_id, text, (extract(epoch from created_at) * 1000)::bigint";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchStatus {
+    /// Sealed in Postgres, the root is not on chain yet.
+    Pending,
+    Published,
+    Failed,
+}
+
+impl BatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchStatus::Pending => "pending",
+            BatchStatus::Published => "published",
+            BatchStatus::Failed => "failed",
+        }
+    }
+}
+
+/// A window of a channel's `!STORE` messages whose Merkle root is anchored on Sui.
+#[derive(Debug, Clone)]
+pub struct ChatBatch {
+    pub id: Uuid,
+    pub channel_id: i64,
+    pub channel_login: String,
+    pub root: Vec<u8>,
+    pub leaf_count: i32,
+    pub first_message_ms: i64,
+    pub last_message_ms: i64,
+}
+
+const BATCH_COLUMNS: &str = "id, channel_id, channel_login, root, leaf_count, \
+     (extract(epoch from first_message_at) * 1000)::bigint, (extract(epoch from last_message_at) * 1000)::bigint";
+
+impl ChatBatch {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get(0),
+            channel_id: row.get(1),
+            channel_login: row.get(2),
+            root: row.get(3),
+            leaf_count: row.get(4),
+            first_message_ms: row.get(5),
+            last_message_ms: row.get(6),
+        }
+    }
+
+    /// Channels with `!STORE` messages that are not in a batch yet.
+    pub async fn unsealed_channels(client: &PgClient) -> Result<Vec<(i64, String)>, GlobalError> {
+        let rows = client
+            .query(
+                "SELECT channel_id, max(channel_login) FROM chat_messages \
+                 WHERE command = '!STORE' AND batch_id IS NULL AND moderated_at IS NULL AND channel_id IS NOT NULL \
+                 GROUP BY channel_id",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Puts up to `max_leaves` of the channel's oldest unbatched `!STORE` messages
+    /// into a new batch and records each message's leaf index.
+    pub async fn seal(
+        client: &mut PgClient,
+        channel_id: i64,
+        channel_login: &str,
+        max_leaves: i64,
+    ) -> Result<Option<Self>, GlobalError> {
+        let transaction = client.transaction().await?;
+        let query = format!(
+            "SELECT {} FROM chat_messages \
+             WHERE channel_id = $1 AND command = '!STORE' AND batch_id IS NULL AND moderated_at IS NULL \
+             ORDER BY created_at, id LIMIT $2 FOR UPDATE SKIP LOCKED",
+            LEAF_COLUMNS
+        );
+        let rows = transaction
+            .query(&query, &[&channel_id, &max_leaves])
+            .await?;
+        let leaves: Vec<ChatLeaf> = rows.iter().map(ChatLeaf::from_row).collect();
+        let hashes = leaves
+            .iter()
+            .map(ChatLeaf::hash)
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(tree) = MerkleTree::new(hashes) else {
+            return Ok(None);
+        };
+
+        let first_ms = leaves[0].created_at_ms as i64;
+        let last_ms = leaves[leaves.len() - 1].created_at_ms as i64;
+        let query = format!(
+            "INSERT INTO chat_batches (channel_id, channel_login, root, leaf_count, first_message_at, last_message_at) \
+             VALUES ($1, $2, $3, $4, to_timestamp($5::bigint / 1000.0) AT TIME ZONE 'UTC', to_timestamp($6::bigint / 1000.0) AT TIME ZONE 'UTC') \
+             RETURNING {}",
+            BATCH_COLUMNS
+        );
+        let row = transaction
+            .query_one(
+                &query,
+                &[
+                    &channel_id,
+                    &channel_login,
+                    &tree.root().to_vec(),
+                    &(tree.leaf_count() as i32),
+                    &first_ms,
+                    &last_ms,
+                ],
+            )
+            .await?;
+        let batch = Self::from_row(&row);
+
+        let ids: Vec<Uuid> = rows.iter().map(|row| row.get(0)).collect();
+        let indexes: Vec<i32> = (0..ids.len() as i32).collect();
+        transaction
+            .execute(
+                "UPDATE chat_messages m SET batch_id = $1, leaf_index = l.leaf_index \
+                 FROM unnest($2::uuid[], $3::int[]) AS l(id, leaf_index) WHERE m.id = l.id",
+                &[&batch.id, &ids, &indexes],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(Some(batch))
+    }
+
+    /// Sealed batches whose root still has to be published, oldest first. Batches
+    /// whose publishing transaction is journaled but not settled are left out,
+    /// they may be on chain already.
+    pub async fn unpublished(client: &PgClient) -> Result<Vec<Self>, GlobalError> {
+        let query = format!(
+            "SELECT {} FROM chat_batches b WHERE status IN ('pending', 'failed') AND NOT EXISTS ( \
+                 SELECT 1 FROM transaction_journal t WHERE t.status IN ('pending', 'confirmed') \
+                 AND t.subject->>'kind' = 'chat_batch' AND t.subject->>'batch_id' = b.id::text \
+             ) ORDER BY created_at",
+            BATCH_COLUMNS
+        );
+        let rows = client.query(&query, &[]).await?;
+        Ok(rows.iter().map(Self::from_row).collect())
+    }
+
+    pub async fn mark_published(
+        transaction: &Transaction<'_>,
+        id: Uuid,
+        digest: &str,
+        object_id: &str,
+    ) -> Result<(), GlobalError> {
+        transaction
+            .execute(
+                "UPDATE chat_batches SET status = $2, digest = $3, object_id = $4, error = NULL WHERE id = $1",
+                &[
+                    &id,
+                    &BatchStatus::Published.as_str(),
+                    &digest,
+                    &object_id,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn mark_failed(&self, client: &PgClient, error: &str) -> Result<(), GlobalError> {
+        client
+            .execute(
+                "UPDATE chat_batches SET status = $2, error = $3 WHERE id = $1",
+                &[&self.id, &BatchStatus::Failed.as_str(), &error],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use crate::GlobalError;
+use crate::chat_log::merkle::{Hash, MerkleTree, leaf_hash, root_from_proof};
+use crate::chat_log::{ChatLeaf, LEAF_COLUMNS};
+use crate::pg::PgClient;
+use crate::sui::anchor::OnChainBatch;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use sui_sdk::SuiClient;
+use sui_sdk::types::base_types::ObjectID;
+use uuid::Uuid;
+
+/// Proves that a `!STORE` message is part of an anchored batch.
+/// Byte fields are hex encoded, so the proof can be shared as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub message_id: String,
+    pub batch_id: String,
+    /// BCS encoded [`ChatLeaf`].
+    pub leaf: String,
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub siblings: Vec<String>,
+    pub root: String,
+    /// `chat_log::ChatBatch` holding the root, `None` until the batch is published.
+    pub batch_object: Option<String>,
+    pub digest: Option<String>,
+}
+
+fn decode_hash(value: &str) -> Result<Hash, GlobalError> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    Hash::try_from(bytes.as_slice()).map_err(|_| format!("{} is not a 32 byte hash", value).into())
+}
+
+impl InclusionProof {
+    /// Builds the proof of a stored message from its batch. Returns `None` when
+    /// the message does not exist or was not batched yet.
+    pub async fn for_message(
+        client: &PgClient,
+        message_id: &Uuid,
+    ) -> Result<Option<Self>, GlobalError> {
+        let row = client
+            .query_opt(
+                "SELECT b.id, b.root, b.leaf_count, b.object_id, b.digest, m.leaf_index \
+                 FROM chat_messages m JOIN chat_batches b ON b.id = m.batch_id WHERE m.id = $1",
+                &[message_id],
+            )
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let batch_id: Uuid = row.get(0);
+        let root: Vec<u8> = row.get(1);
+        let leaf_count: i32 = row.get(2);
+        let leaf_index: i32 = row.get(5);
+
+        let query = format!(
+            "SELECT {} FROM chat_messages WHERE batch_id = $1 ORDER BY leaf_index",
+            LEAF_COLUMNS
+        );
+        let leaves: Vec<ChatLeaf> = client
+            .query(&query, &[&batch_id])
+            .await?
+            .iter()
+            .map(ChatLeaf::from_row)
+            .collect();
+        let hashes = leaves
+            .iter()
+            .map(ChatLeaf::hash)
+            .collect::<Result<Vec<_>, _>>()?;
+        let tree = MerkleTree::new(hashes).ok_or("Batch has no messages")?;
+        // the rows must still hash to what was sealed, or the proof would be useless
+        if tree.leaf_count() != leaf_count as usize || tree.root().as_slice() != root.as_slice() {
+            return Err(format!("Messages of batch {} no longer match its root", batch_id).into());
+        }
+        let index = leaf_index as usize;
+        let siblings = tree.proof(index).ok_or("Leaf index out of range")?;
+
+        Ok(Some(Self {
+            message_id: message_id.to_string(),
+            batch_id: batch_id.to_string(),
+            leaf: hex::encode(leaves[index].to_bcs()?),
+            leaf_index: index as u64,
+            leaf_count: leaf_count as u64,
+            siblings: siblings.iter().map(hex::encode).collect(),
+            root: hex::encode(&root),
+            batch_object: row.get(3),
+            digest: row.get(4),
+        }))
+    }
+
+    /// Checks the proof and returns the proven message. `on_chain` is the batch
+    /// the proof claims to be part of, read from chain; without it the proof is
+    /// only checked against its own root.
+    pub fn verify(&self, on_chain: Option<&OnChainBatch>) -> Result<ChatLeaf, GlobalError> {
+        let leaf_bytes = hex::decode(&self.leaf)?;
+        let leaf: ChatLeaf = bcs::from_bytes(&leaf_bytes)?;
+        if Uuid::from_bytes(leaf.message_id).to_string() != self.message_id {
+            return Err("Leaf belongs to another message".into());
+        }
+        let siblings = self
+            .siblings
+            .iter()
+            .map(|sibling| decode_hash(sibling))
+            .collect::<Result<Vec<_>, _>>()?;
+        let root = root_from_proof(
+            leaf_hash(&leaf_bytes),
+            self.leaf_index as usize,
+            self.leaf_count as usize,
+            &siblings,
+        )
+        .ok_or("Proof does not fit the batch size")?;
+        if root != decode_hash(&self.root)? {
+            return Err("Proof does not lead to the batch root".into());
+        }
+        if let Some(batch) = on_chain {
+            if batch.root.as_slice() != root.as_slice() {
+                return Err(
+                    format!("Proof does not lead to the root of batch {}", batch.id).into(),
+                );
+            }
+            if batch.leaf_count != self.leaf_count {
+                return Err(format!("Batch {} has {} leaves", batch.id, batch.leaf_count).into());
+            }
+            if batch.channel_id != leaf.channel_id {
+                return Err(format!("Batch {} is of another channel", batch.id).into());
+            }
+            if batch.batch_id.as_slice() != Uuid::from_str(&self.batch_id)?.as_bytes() {
+                return Err(format!("Batch {} is not batch {}", batch.id, self.batch_id).into());
+            }
+        }
+        Ok(leaf)
+    }
+
+    /// Reads the published batch named by `batch_object` and checks the proof against it.
+    /// `package` is the `chat_log` package the batch must belong to.
+    pub async fn verify_on_chain(
+        &self,
+        client: &SuiClient,
+        package: Option<ObjectID>,
+    ) -> Result<ChatLeaf, GlobalError> {
+        let object_id = self
+            .batch_object
+            .as_deref()
+            .ok_or("Batch is not published yet")?;
+        let object_id = ObjectID::from_hex_literal(object_id).map_err(|e| anyhow!(e))?;
+        let batch = OnChainBatch::read(client, object_id, package).await?;
+        self.verify(Some(&batch))
+    }
+}
+
+/// A proof and whether it should verify, see `fixtures/chat_log/vectors.json`.
+#[derive(Debug, Deserialize)]
+pub struct ProofVector {
+    pub name: String,
+    pub valid: bool,
+    pub proof: InclusionProof,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vectors() -> Vec<ProofVector> {
+        serde_json::from_str(include_str!("../../fixtures/chat_log/vectors.json")).unwrap()
+    }
+
+    /// The batch a valid proof of `vectors.json` was published as.
+    fn published(proof: &InclusionProof) -> OnChainBatch {
+        let leaf = proof.verify(None).unwrap();
+        OnChainBatch {
+            id: ObjectID::ZERO,
+            batch_id: Uuid::from_str(&proof.batch_id).unwrap().as_bytes().to_vec(),
+            channel_id: leaf.channel_id,
+            root: decode_hash(&proof.root).unwrap().to_vec(),
+            leaf_count: proof.leaf_count,
+            first_message_ms: leaf.created_at_ms,
+            last_message_ms: leaf.created_at_ms,
+        }
+    }
+
+    #[test]
+    fn vectors_verify_as_expected() {
+        let vectors = vectors();
+        assert!(vectors.iter().any(|v| v.valid) && vectors.iter().any(|v| !v.valid));
+        for vector in &vectors {
+            let result = vector.proof.verify(None);
+            assert_eq!(
+                result.is_ok(),
+                vector.valid,
+                "{}: {:?}",
+                vector.name,
+                result.err()
+            );
+        }
+    }
+
+    #[test]
+    fn proofs_are_checked_against_the_published_batch() {
+        let vectors = vectors();
+        let proof = &vectors.iter().find(|v| v.valid).unwrap().proof;
+        let batch = published(proof);
+        assert!(proof.verify(Some(&batch)).is_ok());
+
+        let mut other_root = batch.clone();
+        other_root.root[0] ^= 1;
+        let mut other_size = batch.clone();
+        other_size.leaf_count += 1;
+        let mut other_batch = batch.clone();
+        other_batch.batch_id[15] ^= 1;
+        let mut other_channel = batch.clone();
+        other_channel.channel_id += 1;
+        for batch in [other_root, other_size, other_batch, other_channel] {
+            assert!(proof.verify(Some(&batch)).is_err(), "{:?}", batch);
+        }
+    }
+
+    #[test]
+    fn invalid_vectors_fail_against_the_published_batch_too() {
+        let vectors = vectors();
+        for vector in vectors.iter().filter(|v| !v.valid) {
+            // the batch the proof was cut from, if its own root is still intact
+            let Some(valid) = vectors
+                .iter()
+                .find(|v| v.valid && v.proof.batch_id == vector.proof.batch_id)
+            else {
+                continue;
+            };
+            let batch = published(&valid.proof);
+            assert!(
+                vector.proof.verify(Some(&batch)).is_err(),
+                "{}",
+                vector.name
+            );
+        }
+    }
+}
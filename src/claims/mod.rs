@@ -0,0 +1,358 @@
+pub mod pipeline;
+pub mod rewards;
+pub mod wallets;
+
+use crate::GlobalError;
+use crate::claims::rewards::RewardRule;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use deadpool_postgres::Transaction;
+use std::fmt::Display;
+use std::str::FromStr;
+use sui_sdk::types::base_types::SuiAddress;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+const DEFAULT_CLAIM_MAX_ATTEMPTS: i32 = 5;
+
+/// Attempts after which a requeued claim fails instead, `CLAIM_MAX_ATTEMPTS` env.
+fn claim_max_attempts() -> i32 {
+    env_or("CLAIM_MAX_ATTEMPTS", DEFAULT_CLAIM_MAX_ATTEMPTS)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimStatus {
+    Pending,
+    Processing,
+    Minted,
+    Failed,
+    Revoked,
+    /// The chat message of the claim was deleted by a moderator.
+    Cancelled,
+    /// The oracle signed an attestation, the viewer mints it with `nft::claim::claim`.
+    Attested,
+}
+
+impl ClaimStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClaimStatus::Pending => "pending",
+            ClaimStatus::Processing => "processing",
+            ClaimStatus::Minted => "minted",
+            ClaimStatus::Failed => "failed",
+            ClaimStatus::Revoked => "revoked",
+            ClaimStatus::Cancelled => "cancelled",
+            ClaimStatus::Attested => "attested",
+        }
+    }
+}
+
+impl Display for ClaimStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// What made the oracle queue the mint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimSource {
+    /// A viewer's `!NFT`.
+    Chat,
+    /// A moderator's `!AIRDROP`.
+    Airdrop,
+    /// A sub, raid or cheer that matched a reward rule of the channel.
+    Reward,
+}
+
+impl ClaimSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClaimSource::Chat => "chat",
+            ClaimSource::Airdrop => "airdrop",
+            ClaimSource::Reward => "reward",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewClaim<'a> {
+    pub chat_message_id: Option<Uuid>,
+    pub channel_id: i64,
+    pub channel_login: &'a str,
+    pub user_id: i64,
+    pub recipient: SuiAddress,
+    pub source: ClaimSource,
+    /// Rule that granted the claim, its NFT metadata replaces the channel's.
+    pub reward: Option<&'a RewardRule>,
+}
+
+/// A queued mint of an NFT to a viewer.
+#[derive(Debug, Clone)]
+pub struct ClaimJob {
+    pub id: Uuid,
+    pub channel_id: i64,
+    pub channel_login: String,
+    pub user_id: i64,
+    pub recipient: SuiAddress,
+    pub attempts: i32,
+    pub nft_name: Option<String>,
+    pub nft_description: Option<String>,
+    pub nft_url: Option<String>,
+    /// Reward event, or the source for claims that are not rewards, e.g. `chat` or `sub`.
+    pub event: String,
+    pub created_at_ms: i64,
+}
+
+const JOB_COLUMNS: &str = "id, channel_id, channel_login, user_id, recipient, attempts, nft_name, nft_description, nft_url, \
+     coalesce(reward_event, source), (extract(epoch from created_at) * 1000)::bigint";
+
+impl ClaimJob {
+    fn from_row(row: &Row) -> Result<Self, GlobalError> {
+        let recipient: String = row.get(4);
+        Ok(Self {
+            id: row.get(0),
+            channel_id: row.get(1),
+            channel_login: row.get(2),
+            user_id: row.get(3),
+            recipient: SuiAddress::from_str(&recipient)?,
+            attempts: row.get(5),
+            nft_name: row.get(6),
+            nft_description: row.get(7),
+            nft_url: row.get(8),
+            event: row.get(9),
+            created_at_ms: row.get(10),
+        })
+    }
+
+    /// Queues a claim, linked to the channel's open stream session.
+    pub async fn enqueue(client: &PgClient, claim: &NewClaim<'_>) -> Result<Uuid, GlobalError> {
+        let query = "INSERT INTO claim_jobs (chat_message_id, stream_session_id, channel_id, channel_login, user_id, recipient, source, \
+             reward_event, nft_name, nft_description, nft_url) \
+             VALUES ($1, (SELECT id FROM stream_sessions WHERE broadcaster_id = $2 AND ended_at IS NULL ORDER BY started_at DESC LIMIT 1), $2, $3, $4, $5, $6, \
+             $7, $8, $9, $10) \
+             RETURNING id";
+        let reward = claim.reward;
+        let row = client
+            .query_one(
+                query,
+                &[
+                    &claim.chat_message_id,
+                    &claim.channel_id,
+                    &claim.channel_login,
+                    &claim.user_id,
+                    &claim.recipient.to_string(),
+                    &claim.source.as_str(),
+                    &reward.map(|r| r.event.as_str()),
+                    &reward.and_then(|r| r.nft_name.as_deref()),
+                    &reward.and_then(|r| r.nft_description.as_deref()),
+                    &reward.and_then(|r| r.nft_url.as_deref()),
+                ],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Takes up to `limit` of the oldest pending jobs of channels that are not paused
+    /// and marks them as processing.
+    pub async fn next_pending(client: &PgClient, limit: i64) -> Result<Vec<Self>, GlobalError> {
+        let query = format!(
+            "UPDATE claim_jobs SET status = 'processing', attempts = attempts + 1 \
+             WHERE id IN ( \
+                 SELECT j.id FROM claim_jobs j \
+                 LEFT JOIN pipeline_state p ON p.channel_id = j.channel_id \
+                 WHERE j.status = 'pending' AND NOT coalesce(p.paused, false) \
+                 ORDER BY j.created_at LIMIT $1 FOR UPDATE OF j SKIP LOCKED \
+             ) RETURNING {}",
+            JOB_COLUMNS
+        );
+        let rows = client.query(&query, &[&limit]).await?;
+        let mut jobs = rows
+            .iter()
+            .map(Self::from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        jobs.sort_by_key(|job| job.created_at_ms);
+        Ok(jobs)
+    }
+
+    /// The jobs with `ids`, in the order of `ids`.
+    pub async fn by_ids(client: &PgClient, ids: &[Uuid]) -> Result<Vec<Self>, GlobalError> {
+        let query = format!(
+            "SELECT {} FROM claim_jobs WHERE id = ANY($1) ORDER BY array_position($1, id)",
+            JOB_COLUMNS
+        );
+        let rows = client.query(&query, &[&ids]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    /// Returns jobs left processing by a stopped oracle to the queue, unless a
+    /// journaled transaction that may have minted them is not settled yet.
+    pub async fn requeue_unsubmitted(client: &PgClient) -> Result<u64, GlobalError> {
+        let requeued = client
+            .execute(
+                "UPDATE claim_jobs j SET status = $1 WHERE j.status = 'processing' AND NOT EXISTS ( \
+                     SELECT 1 FROM transaction_journal t \
+                     WHERE t.status IN ('pending', 'confirmed') AND j.id = ANY(t.claim_ids) \
+                 )",
+                &[&ClaimStatus::Pending.as_str()],
+            )
+            .await?;
+        Ok(requeued)
+    }
+
+    /// Stores the mint's digest, NFT and execution outcome.
+    pub async fn mark_minted(
+        transaction: &Transaction<'_>,
+        id: Uuid,
+        digest: &str,
+        nft_object_id: Option<&str>,
+        effects: Option<&serde_json::Value>,
+    ) -> Result<(), GlobalError> {
+        transaction
+            .execute(
+                "UPDATE claim_jobs SET status = $2, digest = $3, nft_object_id = $4, effects = $5, error = NULL \
+                 WHERE id = $1",
+                &[
+                    &id,
+                    &ClaimStatus::Minted.as_str(),
+                    &digest,
+                    &nft_object_id,
+                    &effects,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Reserves the nonce of a new attestation, nonces are never reused.
+    pub async fn next_nonce(client: &PgClient) -> Result<i64, GlobalError> {
+        let row = client
+            .query_one("SELECT nextval('attestation_nonces')", &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    pub async fn mark_attested(
+        client: &PgClient,
+        id: Uuid,
+        nonce: i64,
+        attestation: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<(), GlobalError> {
+        client
+            .execute(
+                "UPDATE claim_jobs SET status = $2, nonce = $3, attestation = $4, attestation_signature = $5, \
+                 oracle_public_key = $6, error = NULL WHERE id = $1",
+                &[
+                    &id,
+                    &ClaimStatus::Attested.as_str(),
+                    &nonce,
+                    &attestation,
+                    &signature,
+                    &public_key,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Signed attestation of a claim as `(payload, signature, public key)`, `None`
+    /// once the claim is cancelled.
+    pub async fn attestation(
+        client: &PgClient,
+        id: &Uuid,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>, Vec<u8>)>, GlobalError> {
+        let row = client
+            .query_opt(
+                "SELECT attestation, attestation_signature, oracle_public_key FROM claim_jobs \
+                 WHERE id = $1 AND status = $2 AND attestation IS NOT NULL",
+                &[id, &ClaimStatus::Attested.as_str()],
+            )
+            .await?;
+        Ok(row.map(|row| (row.get(0), row.get(1), row.get(2))))
+    }
+
+    pub async fn mark_failed(&self, client: &PgClient, error: &str) -> Result<(), GlobalError> {
+        client
+            .execute(
+                "UPDATE claim_jobs SET status = $2, error = $3 WHERE id = $1",
+                &[&self.id, &ClaimStatus::Failed.as_str(), &error],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the processing `jobs` to the queue as they are, e.g. when the RPC
+    /// failed before their transaction was submitted. Jobs that were taken
+    /// `CLAIM_MAX_ATTEMPTS` times fail instead.
+    pub async fn requeue(
+        client: &PgClient,
+        jobs: &[Self],
+        error: &str,
+    ) -> Result<u64, GlobalError> {
+        let ids: Vec<Uuid> = jobs.iter().map(|job| job.id).collect();
+        let requeued = client
+            .execute(
+                "UPDATE claim_jobs SET status = CASE WHEN attempts >= $4 THEN $5 ELSE $2 END, error = $3 \
+                 WHERE id = ANY($1) AND status = 'processing'",
+                &[
+                    &ids,
+                    &ClaimStatus::Pending.as_str(),
+                    &error,
+                    &claim_max_attempts(),
+                    &ClaimStatus::Failed.as_str(),
+                ],
+            )
+            .await?;
+        Ok(requeued)
+    }
+
+    /// Revokes the user's claims in the channel that were not minted yet.
+    pub async fn revoke_pending(
+        client: &PgClient,
+        channel_id: i64,
+        user_id: i64,
+    ) -> Result<u64, GlobalError> {
+        let revoked = client
+            .execute(
+                "UPDATE claim_jobs SET status = $3 WHERE channel_id = $1 AND user_id = $2 AND status = 'pending'",
+                &[&channel_id, &user_id, &ClaimStatus::Revoked.as_str()],
+            )
+            .await?;
+        Ok(revoked)
+    }
+
+    /// Cancels the pending and attested claims that were made by the given chat messages.
+    /// The signature of an attested one stays valid until it expires, it is no longer handed out.
+    pub async fn cancel_for_messages(
+        client: &PgClient,
+        chat_message_ids: &[Uuid],
+    ) -> Result<u64, GlobalError> {
+        if chat_message_ids.is_empty() {
+            return Ok(0);
+        }
+        let cancelled = client
+            .execute(
+                "UPDATE claim_jobs SET status = $2 WHERE chat_message_id = ANY($1) AND status IN ($3, $4)",
+                &[
+                    &chat_message_ids,
+                    &ClaimStatus::Cancelled.as_str(),
+                    &ClaimStatus::Pending.as_str(),
+                    &ClaimStatus::Attested.as_str(),
+                ],
+            )
+            .await?;
+        Ok(cancelled)
+    }
+
+    /// Number of claims of the channel waiting to be minted.
+    pub async fn queue_depth(client: &PgClient, channel_id: i64) -> Result<i64, GlobalError> {
+        let row = client
+            .query_one(
+                "SELECT count(*) FROM claim_jobs WHERE channel_id = $1 AND status IN ('pending', 'processing')",
+                &[&channel_id],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+}
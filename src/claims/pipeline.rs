@@ -0,0 +1,31 @@
+use crate::GlobalError;
+use crate::pg::PgClient;
+
+/// Whether minting is paused in a channel, controlled by `!PAUSE` and `!RESUME`.
+pub struct PipelineState;
+
+impl PipelineState {
+    pub async fn set_paused(
+        client: &PgClient,
+        channel_id: i64,
+        paused: bool,
+        updated_by: i64,
+    ) -> Result<(), GlobalError> {
+        let query = "INSERT INTO pipeline_state (channel_id, paused, updated_by) VALUES ($1, $2, $3) \
+             ON CONFLICT (channel_id) DO UPDATE SET paused = excluded.paused, updated_by = excluded.updated_by";
+        client
+            .execute(query, &[&channel_id, &paused, &updated_by])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_paused(client: &PgClient, channel_id: i64) -> Result<bool, GlobalError> {
+        let row = client
+            .query_opt(
+                "SELECT paused FROM pipeline_state WHERE channel_id = $1",
+                &[&channel_id],
+            )
+            .await?;
+        Ok(row.map(|row| row.get(0)).unwrap_or(false))
+    }
+}
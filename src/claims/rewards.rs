@@ -0,0 +1,109 @@
+use crate::GlobalError;
+use crate::claims::wallets::Wallets;
+use crate::claims::{ClaimJob, ClaimSource, NewClaim};
+use crate::pg::PgClient;
+use crate::twitch::channel_config::ChannelConfig;
+use serde::Deserialize;
+use std::fmt::Display;
+use tracing::info;
+
+/// Chat events that can be rewarded with an NFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewardEvent {
+    /// First subscription, the amount is the number of months.
+    Sub,
+    /// Subscription renewal shared in chat, the amount is the cumulative months.
+    Resub,
+    /// Gifted subscription, rewards the gifter. The amount is the number of gifted months.
+    Subgift,
+    /// Incoming raid, rewards the raider. The amount is the number of viewers.
+    Raid,
+    /// Bits cheered in a chat message, the amount is the number of bits.
+    Cheer,
+}
+
+impl RewardEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewardEvent::Sub => "sub",
+            RewardEvent::Resub => "resub",
+            RewardEvent::Subgift => "subgift",
+            RewardEvent::Raid => "raid",
+            RewardEvent::Cheer => "cheer",
+        }
+    }
+}
+
+impl Display for RewardEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Mints an NFT when a chat event reaches `min_amount`, e.g.
+/// `{"event": "cheer", "min_amount": 1000, "nft_name": "Big cheer"}`.
+/// When several rules of an event match, the one with the highest `min_amount` wins,
+/// so rules can be tiered by sub months or bits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RewardRule {
+    pub event: RewardEvent,
+    #[serde(default)]
+    pub min_amount: u64,
+    /// Metadata of the rewarded NFT, the channel's is used when empty.
+    #[serde(default)]
+    pub nft_name: Option<String>,
+    #[serde(default)]
+    pub nft_description: Option<String>,
+    #[serde(default)]
+    pub nft_url: Option<String>,
+}
+
+/// The highest tier of `rules` reached by `amount` for `event`.
+pub fn select_rule(rules: &[RewardRule], event: RewardEvent, amount: u64) -> Option<&RewardRule> {
+    rules
+        .iter()
+        .filter(|rule| rule.event == event && amount >= rule.min_amount)
+        .max_by_key(|rule| rule.min_amount)
+}
+
+pub struct Rewards;
+
+impl Rewards {
+    /// Queues the reward of `event` for the user, if the channel has a matching rule
+    /// and the user registered a wallet. Returns the id of the queued claim.
+    pub async fn grant(
+        client: &PgClient,
+        channel: &ChannelConfig,
+        channel_id: i64,
+        user_id: i64,
+        event: RewardEvent,
+        amount: u64,
+    ) -> Result<Option<uuid::Uuid>, GlobalError> {
+        let Some(rule) = select_rule(&channel.rewards, event, amount) else {
+            return Ok(None);
+        };
+        let Some(recipient) = Wallets::get(client, user_id).await? else {
+            info!(
+                "User {} earned a {} reward in {} but has no wallet",
+                user_id, event, channel.login
+            );
+            return Ok(None);
+        };
+        let claim = NewClaim {
+            chat_message_id: None,
+            channel_id,
+            channel_login: &channel.login,
+            user_id,
+            recipient,
+            source: ClaimSource::Reward,
+            reward: Some(rule),
+        };
+        let job_id = ClaimJob::enqueue(client, &claim).await?;
+        info!(
+            "Queued {} reward {} ({} >= {}) for user {}",
+            event, job_id, amount, rule.min_amount, user_id
+        );
+        Ok(Some(job_id))
+    }
+}
@@ -0,0 +1,38 @@
+use crate::GlobalError;
+use crate::pg::PgClient;
+use std::str::FromStr;
+use sui_sdk::types::base_types::SuiAddress;
+
+/// Sui addresses viewers registered with `!NFT <address>`.
+pub struct Wallets;
+
+impl Wallets {
+    pub async fn set(
+        client: &PgClient,
+        user_id: i64,
+        address: &SuiAddress,
+    ) -> Result<(), GlobalError> {
+        let query = "INSERT INTO viewer_wallets (user_id, address) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE SET address = excluded.address";
+        client
+            .execute(query, &[&user_id, &address.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(client: &PgClient, user_id: i64) -> Result<Option<SuiAddress>, GlobalError> {
+        let row = client
+            .query_opt(
+                "SELECT address FROM viewer_wallets WHERE user_id = $1",
+                &[&user_id],
+            )
+            .await?;
+        match row {
+            Some(row) => {
+                let address: String = row.get(0);
+                Ok(Some(SuiAddress::from_str(&address)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Subcommands run instead of the oracle, e.g. `twitch-sui-oracle prove <message-id>`.
+
+use crate::chat_log::proof::{InclusionProof, ProofVector};
+use crate::claims::ClaimJob;
+use crate::pg::PgConnect;
+use crate::sui;
+use crate::sui::attestation::Attestation;
+use anyhow::anyhow;
+use serde_json::json;
+use std::error::Error;
+use std::fs;
+use std::str::FromStr;
+use sui_sdk::types::base_types::ObjectID;
+use uuid::Uuid;
+
+const USAGE: &str = "Usage:
+  twitch-sui-oracle [--dry-run]                     listen to chat, --dry-run simulates every transaction
+  twitch-sui-oracle prove <message-id>              print the inclusion proof of a !STORE message
+  twitch-sui-oracle verify <proof.json>             check a proof against its published batch on chain
+  twitch-sui-oracle verify --offline <proof.json>   check a proof against its own root only
+  twitch-sui-oracle verify --vectors <vectors.json> check a file of test vectors
+  twitch-sui-oracle attestation <claim-id>          print the arguments of nft::claim::claim for a claim";
+
+pub async fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["prove", message_id] => prove(message_id).await,
+        ["attestation", claim_id] => attestation(claim_id).await,
+        ["verify", "--vectors", path] => verify_vectors(path),
+        ["verify", "--offline", path] => verify(path, false).await,
+        ["verify", path] => verify(path, true).await,
+        _ => Err(USAGE.into()),
+    }
+}
+
+async fn prove(message_id: &str) -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let message_id = Uuid::from_str(message_id)?;
+    let pool = PgConnect::create_pool_from_env()?;
+    let client = pool.get().await?;
+    let proof = InclusionProof::for_message(&client, &message_id)
+        .await
+        .map_err(|e| e as Box<dyn Error>)?;
+    let Some(proof) = proof else {
+        return Err(format!("Message {} is not in a batch yet", message_id).into());
+    };
+    // a published batch must hold the root the database sealed
+    if proof.batch_object.is_some() {
+        proof
+            .verify_on_chain(&sui::read_client().await?, chat_log_package()?)
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+    }
+    println!("{}", serde_json::to_string_pretty(&proof)?);
+    Ok(())
+}
+
+/// `CHAT_LOG_PACKAGE`, the package genuine batches belong to, if it is set.
+fn chat_log_package() -> Result<Option<ObjectID>, Box<dyn Error>> {
+    match std::env::var("CHAT_LOG_PACKAGE") {
+        Ok(package) => Ok(Some(
+            ObjectID::from_hex_literal(&package).map_err(|e| anyhow!(e))?,
+        )),
+        Err(_) => {
+            eprintln!("CHAT_LOG_PACKAGE is not set, the batch's package is not checked");
+            Ok(None)
+        }
+    }
+}
+
+async fn attestation(claim_id: &str) -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+    let claim_id = Uuid::from_str(claim_id)?;
+    let pool = PgConnect::create_pool_from_env()?;
+    let client = pool.get().await?;
+    let attestation = ClaimJob::attestation(&client, &claim_id)
+        .await
+        .map_err(|e| e as Box<dyn Error>)?;
+    let Some((payload, signature, public_key)) = attestation else {
+        return Err(format!("Claim {} has no attestation", claim_id).into());
+    };
+    let attestation: Attestation = bcs::from_bytes(&payload)?;
+    // in the order of the Move entry function, the clock is the shared 0x6
+    let arguments = [
+        ("registry", json!(attestation.registry.to_string())),
+        ("twitch_user_id", json!(attestation.twitch_user_id)),
+        ("channel_id", json!(attestation.channel_id)),
+        ("event", json!(String::from_utf8_lossy(&attestation.event))),
+        ("timestamp_ms", json!(attestation.timestamp_ms)),
+        ("recipient", json!(attestation.recipient.to_string())),
+        ("nonce", json!(attestation.nonce)),
+        ("expires_at_ms", json!(attestation.expires_at_ms)),
+        ("name", json!(String::from_utf8_lossy(&attestation.name))),
+        (
+            "description",
+            json!(String::from_utf8_lossy(&attestation.description)),
+        ),
+        ("url", json!(String::from_utf8_lossy(&attestation.url))),
+        ("signature", json!(format!("0x{}", hex::encode(&signature)))),
+        ("clock", json!("0x6")),
+    ]
+    .map(|(name, value)| json!({"name": name, "value": value}));
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({
+            "payload": format!("0x{}", hex::encode(&payload)),
+            "oracle_public_key": format!("0x{}", hex::encode(&public_key)),
+            "arguments": arguments,
+        }))?
+    );
+    Ok(())
+}
+
+async fn verify(path: &str, on_chain: bool) -> Result<(), Box<dyn Error>> {
+    let proof: InclusionProof = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let leaf = if on_chain {
+        dotenvy::dotenv().ok();
+        proof
+            .verify_on_chain(&sui::read_client().await?, chat_log_package()?)
+            .await
+    } else {
+        proof.verify(None)
+    };
+    let leaf = leaf.map_err(|e| e as Box<dyn Error>)?;
+    println!(
+        "Valid: message {} of user {} in channel {}: {:?}",
+        proof.message_id, leaf.user_id, leaf.channel_id, leaf.text
+    );
+    Ok(())
+}
+
+fn verify_vectors(path: &str) -> Result<(), Box<dyn Error>> {
+    let vectors: Vec<ProofVector> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let mut mismatches = 0;
+    for vector in &vectors {
+        let result = vector.proof.verify(None);
+        let ok = result.is_ok() == vector.valid;
+        if !ok {
+            mismatches += 1;
+        }
+        println!(
+            "{} {}: {}",
+            if ok { "ok  " } else { "FAIL" },
+            vector.name,
+            result
+                .map(|_| "valid".to_string())
+                .unwrap_or_else(|e| e.to_string())
+        );
+    }
+    match mismatches {
+        0 => Ok(()),
+        n => Err(format!("{} of {} vectors failed", n, vectors.len()).into()),
+    }
+}
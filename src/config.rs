@@ -0,0 +1,23 @@
+//! Settings read from the environment.
+
+use std::str::FromStr;
+use tracing::warn;
+
+/// Parses the env var `name`, `None` when it is not set. A value that does not
+/// parse is logged and ignored, so a typo falls back to the default instead of
+/// stopping the oracle.
+pub fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            warn!("Ignoring {}={:?}, it is not a valid value", name, value);
+            None
+        }
+    }
+}
+
+/// Parses the env var `name`, `default` when it is not set or not valid.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
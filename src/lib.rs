@@ -0,0 +1,9 @@
+pub mod chat_log;
+pub mod claims;
+pub mod cli;
+pub mod config;
+pub mod pg;
+pub mod sui;
+pub mod twitch;
+
+pub type GlobalError = Box<dyn std::error::Error + Send + Sync>;
@@ -1,18 +1,29 @@
-use crate::twitch::TwitchApi;
-mod pg;
-use crate::pg::pg::{PgClient, PgConnect};
-use std::env;
-
-mod sui;
-mod twitch;
+use std::sync::Arc;
+use twitch_sui_oracle::cli;
+use twitch_sui_oracle::pg::PgConnect;
+use twitch_sui_oracle::sui::Oracle;
+use twitch_sui_oracle::sui::anchor::ChatAnchor;
+use twitch_sui_oracle::sui::gas::GasManager;
+use twitch_sui_oracle::sui::journal::JournalResolver;
+use twitch_sui_oracle::sui::minter::{ClaimMode, Minter};
+use twitch_sui_oracle::sui::stream_feed::StreamFeedPublisher;
+use twitch_sui_oracle::twitch::TwitchApi;
+use twitch_sui_oracle::twitch::channel_config::ChannelsConfig;
+use twitch_sui_oracle::twitch::commands::{CommandRegistry, CommandServices};
+use twitch_sui_oracle::twitch::stream_session::StreamSession;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // transactions are built, signed and dry-run but never executed
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    args.retain(|arg| arg != "--dry-run");
+    if !args.is_empty() {
+        return cli::run(&args).await;
+    }
     dotenvy::dotenv().expect("Env file is not loaded into the project");
 
-    let coin = sui::start_client().await?;
-    return Ok(());
     // postgres migration
     let pool = PgConnect::create_pool_from_env()?;
     let client = pool.get().await?;
@@ -23,21 +34,100 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .get_and_store_token()
         .await
         .expect("Error getting access token");
+    let twitch_client = Arc::new(twitch_client);
+
+    let channels = Arc::new(ChannelsConfig::from_env()?);
+
+    // claims are still queued without a wallet, they are minted once the oracle is configured
+    let oracle = match Oracle::from_env().await {
+        Ok(mut oracle) => {
+            oracle.dry_run(dry_run).journal(pool.clone());
+            tracing::info!("Oracle wallet {}, dry run {}", oracle.address(), dry_run);
+            Some(Arc::new(oracle))
+        }
+        Err(e) => {
+            tracing::error!("Oracle not configured, minting is disabled: {:?}", e);
+            None
+        }
+    };
+    if let Some(oracle) = &oracle {
+        let mode = ClaimMode::from_env()?;
+        tracing::info!("Claim mode {:?}", mode);
+        // settle what the last run left before anything spends gas again
+        if let Err(e) = oracle.recover().await {
+            tracing::error!("Failed to recover journaled transactions: {:?}", e);
+        }
+        tokio::spawn(JournalResolver::new(oracle.clone()).run());
+        tokio::spawn(GasManager::new(oracle.clone()).run());
+        tokio::spawn(Minter::new(oracle.clone(), pool.clone(), channels.clone(), mode).run());
+        match ChatAnchor::from_env(oracle.clone(), pool.clone())? {
+            Some(anchor) => {
+                tokio::spawn(anchor.run());
+            }
+            None => {
+                tracing::info!("CHAT_LOG_PACKAGE or CHAT_LOG_CAP not set, !STORE is not anchored")
+            }
+        }
+        match StreamFeedPublisher::from_env(
+            oracle.clone(),
+            pool.clone(),
+            twitch_client.clone(),
+            channels.clone(),
+        )? {
+            Some(publisher) => {
+                tokio::spawn(publisher.run());
+            }
+            None => tracing::info!(
+                "STREAM_FEED_PACKAGE or STREAM_FEED_CAP not set, stream stats are not published"
+            ),
+        }
+    }
 
-    let streamer = env::var("STREAMER").unwrap();
-    let stream_info = twitch_client
-        .get_stream_info(&streamer)
+    for streamer in channels.logins() {
+        match twitch_client.resolve_login(&client, streamer).await {
+            Ok(Some(user)) => tracing::info!("Channel {} has broadcaster id {}", streamer, user.id),
+            Ok(None) => tracing::error!("Twitch user {} does not exist", streamer),
+            Err(e) => tracing::error!("Failed to resolve channel {}: {:?}", streamer, e),
+        }
+        tokio::spawn(StreamSession::track(
+            pool.clone(),
+            twitch_client.clone(),
+            streamer.clone(),
+        ));
+    }
+    let logins: Vec<&str> = channels.logins().map(String::as_str).collect();
+    let streams = twitch_client
+        .get_streams(&logins)
         .await
         .expect("Error getting stream info");
-    tracing::info!("Streamer is online with viewers");
-    match stream_info {
-        Some(info) => {
-            tracing::info!("Streamer is online with {} viewers", info.viewer_count);
-            TwitchApi::listen_to_chat().await?;
-        }
-        None => {
-            tracing::error!("Streamer is offline {}", streamer);
+    for streamer in &logins {
+        match streams
+            .iter()
+            .find(|s| s.user_login.eq_ignore_ascii_case(streamer))
+        {
+            Some(info) => tracing::info!(
+                "Streamer {} is online with {} viewers",
+                streamer,
+                info.viewer_count
+            ),
+            None => tracing::error!("Streamer is offline {}", streamer),
         }
     }
+    let any_online = !streams.is_empty();
+    if any_online {
+        let services = CommandServices {
+            pool: pool.clone(),
+            twitch: twitch_client.clone(),
+            oracle,
+        };
+        TwitchApi::listen_to_chat(
+            channels,
+            Arc::new(CommandRegistry::with_defaults()),
+            services,
+        )
+        .await?;
+    } else {
+        tracing::error!("All configured streamers are offline");
+    }
     Ok(())
 }
@@ -4,10 +4,8 @@ use std::io::Error;
 use std::num::ParseIntError;
 use std::{env, fs};
 use tokio_postgres::{Client, NoTls};
-use tracing::{error, info};
+use tracing::info;
 
-const WORKERS: usize = 16;
-const ITERATIONS: usize = 1000;
 pub type PgClient = deadpool::managed::Object<Manager>;
 
 pub struct PgConnect {
@@ -63,9 +61,12 @@ impl PgConnect {
         }
     }
     pub async fn run_migrations(client: &Client) -> Result<(), Box<dyn StdError>> {
-        let paths = fs::read_dir("./migrations")?;
-        for file in paths {
-            let file_name = file?.path();
+        // migrations are applied in file name order, so later files can rely on earlier tables
+        let mut paths = fs::read_dir("./migrations")?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, Error>>()?;
+        paths.sort();
+        for file_name in paths {
             let sql = std::fs::read_to_string(file_name)?;
             client.batch_execute(&sql).await?;
             info!("Executed migration {sql}");
@@ -0,0 +1,166 @@
+use crate::GlobalError;
+use crate::chat_log::ChatBatch;
+use crate::config::env_or;
+use crate::sui::Oracle;
+use crate::sui::outcome::ExecutionError;
+use anyhow::{anyhow, bail};
+use deadpool_postgres::Pool;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use sui_sdk::SuiClient;
+use sui_sdk::rpc_types::{SuiObjectDataOptions, SuiRawData};
+use sui_sdk::types::base_types::ObjectID;
+use tracing::{error, info, warn};
+
+const DEFAULT_ANCHOR_WINDOW_SECS: u64 = 300;
+const DEFAULT_ANCHOR_MAX_LEAVES: i64 = 4096;
+
+/// A published `chat_log::ChatBatch`, field by field as the Move struct is BCS encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OnChainBatch {
+    pub id: ObjectID,
+    /// Bytes of the `chat_batches` row's UUID.
+    pub batch_id: Vec<u8>,
+    pub channel_id: u64,
+    pub root: Vec<u8>,
+    pub leaf_count: u64,
+    pub first_message_ms: u64,
+    pub last_message_ms: u64,
+}
+
+impl OnChainBatch {
+    /// Reads the batch object `object_id`. Fails unless it is a `chat_log::ChatBatch`
+    /// of `package`, when given, so a look-alike published by someone else is refused.
+    pub async fn read(
+        client: &SuiClient,
+        object_id: ObjectID,
+        package: Option<ObjectID>,
+    ) -> Result<Self, anyhow::Error> {
+        let response = client
+            .read_api()
+            .get_object_with_options(object_id, SuiObjectDataOptions::new().with_bcs())
+            .await?;
+        let data = response
+            .data
+            .ok_or_else(|| anyhow!("Object {} does not exist", object_id))?;
+        let Some(SuiRawData::MoveObject(object)) = data.bcs else {
+            bail!("Object {} is not a Move object", object_id);
+        };
+        let type_ = &object.type_;
+        if type_.module.as_str() != "chat_log" || type_.name.as_str() != "ChatBatch" {
+            bail!(
+                "Object {} is a {}, not a chat_log::ChatBatch",
+                object_id,
+                type_
+            );
+        }
+        if let Some(package) = package
+            && ObjectID::from(type_.address) != package
+        {
+            bail!(
+                "Batch {} belongs to package {}, not {}",
+                object_id,
+                type_.address,
+                package
+            );
+        }
+        Ok(bcs::from_bytes(&object.bcs_bytes)?)
+    }
+}
+
+/// Seals each channel's `!STORE` messages into a batch every window and publishes
+/// the batch's Merkle root on Sui.
+pub struct ChatAnchor {
+    oracle: Arc<Oracle>,
+    pool: Pool,
+    /// `chat_log` package, `CHAT_LOG_PACKAGE` env.
+    package: ObjectID,
+    /// `AnchorCap` owned by the oracle, `CHAT_LOG_CAP` env.
+    cap: ObjectID,
+}
+
+impl ChatAnchor {
+    /// Returns `None` when `CHAT_LOG_PACKAGE` or `CHAT_LOG_CAP` is not set.
+    pub fn from_env(oracle: Arc<Oracle>, pool: Pool) -> Result<Option<Self>, anyhow::Error> {
+        let (Ok(package), Ok(cap)) = (
+            std::env::var("CHAT_LOG_PACKAGE"),
+            std::env::var("CHAT_LOG_CAP"),
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            oracle,
+            pool,
+            package: ObjectID::from_hex_literal(&package).map_err(|e| anyhow!(e))?,
+            cap: ObjectID::from_hex_literal(&cap).map_err(|e| anyhow!(e))?,
+        }))
+    }
+
+    /// Seals the unbatched messages of every channel.
+    async fn seal_all(&self, max_leaves: i64) -> Result<(), GlobalError> {
+        let mut client = self.pool.get().await?;
+        for (channel_id, channel_login) in ChatBatch::unsealed_channels(&client).await? {
+            if let Some(batch) =
+                ChatBatch::seal(&mut client, channel_id, &channel_login, max_leaves).await?
+            {
+                info!(
+                    "Sealed batch {} of {} with {} message(s)",
+                    batch.id, channel_login, batch.leaf_count
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes every sealed batch that is not on chain yet, failed ones included.
+    async fn publish_all(&self) -> Result<(), GlobalError> {
+        let client = self.pool.get().await?;
+        for batch in ChatBatch::unpublished(&client).await? {
+            let published = self
+                .oracle
+                .publish_chat_batch(self.package, self.cap, &batch)
+                .await;
+            match published {
+                // the batch is marked published when its transaction is settled
+                Ok((digest, object_id)) => {
+                    info!("Published batch {} as {}: {}", batch.id, object_id, digest)
+                }
+                Err(e)
+                    if matches!(
+                        e.downcast_ref(),
+                        Some(
+                            ExecutionError::Unconfirmed { .. } | ExecutionError::MissingEffects(_)
+                        )
+                    ) =>
+                {
+                    // it may still be published, the journal settles it
+                    warn!("Publishing batch {} is not confirmed: {:?}", batch.id, e);
+                }
+                Err(e) if self.oracle.is_dry_run() => {
+                    warn!("Dry run of batch {} failed: {:?}", batch.id, e);
+                }
+                Err(e) => {
+                    error!("Failed to publish batch {}: {:?}", batch.id, e);
+                    batch.mark_failed(&client, &e.to_string()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        let window_secs = env_or("ANCHOR_WINDOW_SECS", DEFAULT_ANCHOR_WINDOW_SECS);
+        let max_leaves = env_or("ANCHOR_MAX_LEAVES", DEFAULT_ANCHOR_MAX_LEAVES);
+        let mut interval = tokio::time::interval(Duration::from_secs(window_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.seal_all(max_leaves).await {
+                error!("Failed to seal chat batches: {:?}", e);
+            }
+            if let Err(e) = self.publish_all().await {
+                error!("Failed to publish chat batches: {:?}", e);
+            }
+        }
+    }
+}
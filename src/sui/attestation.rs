@@ -0,0 +1,89 @@
+use crate::sui::Oracle;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sui_keys::keystore::AccountKeystore;
+use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::types::crypto::{SignatureScheme, SuiSignature};
+
+/// "Twitch user X did event Y at time T for address A", signed by the oracle as BCS
+/// so `nft::claim::claim` can rebuild and verify it. Field order and types must match
+/// the Move `Attestation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    /// `ClaimRegistry` the attestation is redeemed at, so it cannot be replayed elsewhere.
+    pub registry: ObjectID,
+    pub twitch_user_id: u64,
+    pub channel_id: u64,
+    pub event: Vec<u8>,
+    pub timestamp_ms: u64,
+    pub recipient: SuiAddress,
+    pub nonce: u64,
+    pub expires_at_ms: u64,
+    pub name: Vec<u8>,
+    pub description: Vec<u8>,
+    pub url: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SignedAttestation {
+    pub payload: Vec<u8>,
+    /// Raw 64 byte ed25519 signature over `payload`.
+    pub signature: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+impl Oracle {
+    /// Signs the BCS encoding of `attestation` with the oracle key, which has to be ed25519.
+    pub fn attest(&self, attestation: &Attestation) -> Result<SignedAttestation, anyhow::Error> {
+        let payload = bcs::to_bytes(attestation)?;
+        let signature = self
+            .keystore
+            .sign_hashed(&self.address, &payload)
+            .map_err(|e| anyhow!(e))?;
+        if signature.scheme() != SignatureScheme::ED25519 {
+            return Err(anyhow!(
+                "Oracle key of {} is {:?}, attestations need ed25519",
+                self.address,
+                signature.scheme()
+            ));
+        }
+        Ok(SignedAttestation {
+            payload,
+            signature: signature.signature_bytes().to_vec(),
+            public_key: signature.public_key_bytes().to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// BCS of the attestation the Move `claim_tests::test_attestation_layout` encodes.
+    const ATTESTATION_BCS: &str = "000000000000000000000000000000000000000000000000000000000000c1a14478760800000000611b81280000000004636861740068e5cf8b0100000000000000000000000000000000000000000000000000000000000000000b0b0700000000000000c08feecf8b01000013616e6469736b65792073747265616d204e465426436c61696d656420696e20746865205477697463682063686174206f6620616e6469736b65791e68747470733a2f2f7777772e7477697463682e74762f616e6469736b6579";
+
+    #[test]
+    fn bcs_matches_the_move_attestation() {
+        let attestation = Attestation {
+            registry: ObjectID::from_hex_literal("0xc1a1").unwrap(),
+            twitch_user_id: 141981764,
+            channel_id: 679549793,
+            event: b"chat".to_vec(),
+            timestamp_ms: 1700000000000,
+            recipient: SuiAddress::from_str(
+                "0x0000000000000000000000000000000000000000000000000000000000000b0b",
+            )
+            .unwrap(),
+            nonce: 7,
+            expires_at_ms: 1700000600000,
+            name: b"andiskey stream NFT".to_vec(),
+            description: b"Claimed in the Twitch chat of andiskey".to_vec(),
+            url: b"https://www.twitch.tv/andiskey".to_vec(),
+        };
+        assert_eq!(
+            hex::encode(bcs::to_bytes(&attestation).unwrap()),
+            ATTESTATION_BCS
+        );
+    }
+}
@@ -0,0 +1,90 @@
+module chat_log::chat_log;
+
+use sui::event;
+
+const EInvalidRoot: u64 = 0;
+const EEmptyBatch: u64 = 1;
+
+/// Allows publishing batches, held by the oracle.
+public struct AnchorCap has key, store {
+    id: UID
+}
+
+/// Merkle root over a batch of a channel's `!STORE` messages.
+/// Frozen when published, so the root can never change.
+public struct ChatBatch has key, store {
+    id: UID,
+    /// Id of the batch in the oracle's database
+    batch_id: vector<u8>,
+    channel_id: u64,
+    /// sha256 root, see the oracle's `chat_log::merkle` for the leaf encoding
+    root: vector<u8>,
+    leaf_count: u64,
+    first_message_ms: u64,
+    last_message_ms: u64
+}
+
+public struct BatchPublished has copy, drop {
+    batch: ID,
+    batch_id: vector<u8>,
+    channel_id: u64,
+    root: vector<u8>,
+    leaf_count: u64
+}
+
+fun init(ctx: &mut TxContext) {
+    transfer::transfer(AnchorCap { id: object::new(ctx) }, ctx.sender());
+}
+
+public fun publish_batch(
+    _: &AnchorCap,
+    batch_id: vector<u8>,
+    channel_id: u64,
+    root: vector<u8>,
+    leaf_count: u64,
+    first_message_ms: u64,
+    last_message_ms: u64,
+    ctx: &mut TxContext
+) {
+    assert!(root.length() == 32, EInvalidRoot);
+    assert!(leaf_count > 0, EEmptyBatch);
+    let batch = ChatBatch {
+        id: object::new(ctx),
+        batch_id,
+        channel_id,
+        root,
+        leaf_count,
+        first_message_ms,
+        last_message_ms
+    };
+    event::emit(BatchPublished {
+        batch: object::id(&batch),
+        batch_id,
+        channel_id,
+        root,
+        leaf_count
+    });
+    transfer::freeze_object(batch);
+}
+
+public fun batch_id(batch: &ChatBatch): &vector<u8> {
+    &batch.batch_id
+}
+
+public fun channel_id(batch: &ChatBatch): u64 {
+    batch.channel_id
+}
+
+/// Get the batch's Merkle `root`
+public fun root(batch: &ChatBatch): &vector<u8> {
+    &batch.root
+}
+
+public fun leaf_count(batch: &ChatBatch): u64 {
+    batch.leaf_count
+}
+
+#[test_only]
+public fun init_for_testing(ctx: &mut TxContext) {
+    init(ctx);
+}
@@ -0,0 +1,39 @@
+#[test_only]
+module chat_log::chat_log_tests;
+
+use chat_log::chat_log::{Self, AnchorCap, ChatBatch};
+use sui::test_scenario;
+
+const ORACLE: address = @0xA;
+
+#[test]
+fun test_publish_batch() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    chat_log::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    {
+        let cap = scenario.take_from_sender<AnchorCap>();
+        let root = x"0000000000000000000000000000000000000000000000000000000000000001";
+        chat_log::publish_batch(&cap, b"batch", 42, root, 3, 1, 2, scenario.ctx());
+        scenario.return_to_sender(cap);
+    };
+    scenario.next_tx(ORACLE);
+    {
+        let batch = scenario.take_immutable<ChatBatch>();
+        assert!(chat_log::channel_id(&batch) == 42);
+        assert!(chat_log::leaf_count(&batch) == 3);
+        test_scenario::return_immutable(batch);
+    };
+    scenario.end();
+}
+
+#[test, expected_failure(abort_code = ::chat_log::chat_log::EInvalidRoot)]
+fun test_publish_short_root() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    chat_log::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    let cap = scenario.take_from_sender<AnchorCap>();
+    chat_log::publish_batch(&cap, b"batch", 42, x"01", 1, 1, 1, scenario.ctx());
+    scenario.return_to_sender(cap);
+    scenario.end();
+}
@@ -0,0 +1,192 @@
+module nft::claim;
+
+use nft::nft::{Self, MintAdminCap};
+use std::bcs;
+use sui::clock::Clock;
+use sui::ed25519;
+use sui::table::{Self, Table};
+
+const EInvalidSignature: u64 = 0;
+const ENonceUsed: u64 = 1;
+const EExpired: u64 = 2;
+const EOracleKeyNotSet: u64 = 3;
+
+/// Allows rotating the oracle key
+public struct AdminCap has key, store {
+    id: UID
+}
+
+/// Shared object that checks oracle attestations and remembers redeemed nonces
+public struct ClaimRegistry has key {
+    id: UID,
+    /// ed25519 public key of the oracle, 32 bytes
+    oracle_public_key: vector<u8>,
+    used_nonces: Table<u64, bool>
+}
+
+/// The message the oracle signs, as BCS. Field order and types must match the
+/// oracle's `Attestation`, or no signature will verify. Both sides are tested against
+/// the same encoding, see `claim_tests::test_attestation_layout`.
+public struct Attestation has copy, drop {
+    registry: ID,
+    twitch_user_id: u64,
+    channel_id: u64,
+    /// e.g. `chat`, `airdrop` or `sub`
+    event: vector<u8>,
+    timestamp_ms: u64,
+    recipient: address,
+    nonce: u64,
+    expires_at_ms: u64,
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>
+}
+
+fun init(ctx: &mut TxContext) {
+    new_registry(ctx);
+}
+
+/// Creates another registry and its `AdminCap`, e.g. when this module was added
+/// by an upgrade and `init` never ran
+entry fun create_registry(_: &MintAdminCap, ctx: &mut TxContext) {
+    new_registry(ctx);
+}
+
+fun new_registry(ctx: &mut TxContext) {
+    transfer::transfer(AdminCap { id: object::new(ctx) }, ctx.sender());
+    transfer::share_object(ClaimRegistry {
+        id: object::new(ctx),
+        oracle_public_key: vector[],
+        used_nonces: table::new(ctx)
+    });
+}
+
+public fun set_oracle_key(_: &AdminCap, registry: &mut ClaimRegistry, public_key: vector<u8>) {
+    registry.oracle_public_key = public_key;
+}
+
+public fun is_nonce_used(registry: &ClaimRegistry, nonce: u64): bool {
+    registry.used_nonces.contains(nonce)
+}
+
+/// Mints the attested NFT to `recipient`. Anyone can submit the attestation and pay the gas,
+/// the NFT always goes to the attested address.
+entry fun claim(
+    registry: &mut ClaimRegistry,
+    twitch_user_id: u64,
+    channel_id: u64,
+    event: vector<u8>,
+    timestamp_ms: u64,
+    recipient: address,
+    nonce: u64,
+    expires_at_ms: u64,
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>,
+    signature: vector<u8>,
+    clock: &Clock,
+    ctx: &mut TxContext
+) {
+    assert!(!registry.oracle_public_key.is_empty(), EOracleKeyNotSet);
+    assert!(clock.timestamp_ms() <= expires_at_ms, EExpired);
+    let attestation = Attestation {
+        registry: object::id(registry),
+        twitch_user_id,
+        channel_id,
+        event,
+        timestamp_ms,
+        recipient,
+        nonce,
+        expires_at_ms,
+        name,
+        description,
+        url
+    };
+    let message = bcs::to_bytes(&attestation);
+    assert!(ed25519::ed25519_verify(&signature, &registry.oracle_public_key, &message), EInvalidSignature);
+    assert!(!registry.used_nonces.contains(nonce), ENonceUsed);
+    registry.used_nonces.add(nonce, true);
+
+    let nft = nft::new(name, description, url, ctx);
+    transfer::public_transfer(nft, recipient);
+}
+
+#[test_only]
+public fun new_registry_for_testing(oracle_public_key: vector<u8>, ctx: &mut TxContext): ClaimRegistry {
+    ClaimRegistry {
+        id: object::new(ctx),
+        oracle_public_key,
+        used_nonces: table::new(ctx)
+    }
+}
+
+#[test_only]
+public fun destroy_registry_for_testing(registry: ClaimRegistry) {
+    let ClaimRegistry { id, oracle_public_key: _, used_nonces } = registry;
+    id.delete();
+    used_nonces.drop();
+}
+
+#[test_only]
+public fun attestation_bytes_for_testing(
+    registry: ID,
+    twitch_user_id: u64,
+    channel_id: u64,
+    event: vector<u8>,
+    timestamp_ms: u64,
+    recipient: address,
+    nonce: u64,
+    expires_at_ms: u64,
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>
+): vector<u8> {
+    bcs::to_bytes(&Attestation {
+        registry,
+        twitch_user_id,
+        channel_id,
+        event,
+        timestamp_ms,
+        recipient,
+        nonce,
+        expires_at_ms,
+        name,
+        description,
+        url
+    })
+}
+
+#[test_only]
+public fun claim_for_testing(
+    registry: &mut ClaimRegistry,
+    twitch_user_id: u64,
+    channel_id: u64,
+    event: vector<u8>,
+    timestamp_ms: u64,
+    recipient: address,
+    nonce: u64,
+    expires_at_ms: u64,
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>,
+    signature: vector<u8>,
+    clock: &Clock,
+    ctx: &mut TxContext
+) {
+    claim(
+        registry,
+        twitch_user_id,
+        channel_id,
+        event,
+        timestamp_ms,
+        recipient,
+        nonce,
+        expires_at_ms,
+        name,
+        description,
+        url,
+        signature,
+        clock,
+        ctx
+    );
+}
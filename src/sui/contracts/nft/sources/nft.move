@@ -2,6 +2,21 @@ module nft::nft;
 
 use std::string;
 use sui::url::{Self, Url};
+use sui::vec_set::{Self, VecSet};
+
+const ENotMinter: u64 = 0;
+const EDeprecated: u64 = 1;
+
+/// Allows changing who may mint
+public struct MintAdminCap has key, store {
+    id: UID
+}
+
+/// Shared allowlist of the addresses that may mint, e.g. the oracle
+public struct Minters has key {
+    id: UID,
+    addresses: VecSet<address>
+}
 
 public struct EmoNFT has key, store {
     id: UID,
@@ -10,6 +25,30 @@ public struct EmoNFT has key, store {
     url: Url
 }
 
+fun init(ctx: &mut TxContext) {
+    transfer::transfer(MintAdminCap { id: object::new(ctx) }, ctx.sender());
+    transfer::share_object(Minters {
+        id: object::new(ctx),
+        addresses: vec_set::singleton(ctx.sender())
+    });
+}
+
+public fun add_minter(_: &MintAdminCap, minters: &mut Minters, minter: address) {
+    if (!minters.addresses.contains(&minter)) {
+        minters.addresses.insert(minter);
+    }
+}
+
+public fun remove_minter(_: &MintAdminCap, minters: &mut Minters, minter: address) {
+    if (minters.addresses.contains(&minter)) {
+        minters.addresses.remove(&minter);
+    }
+}
+
+public fun is_minter(minters: &Minters, minter: address): bool {
+    minters.addresses.contains(&minter)
+}
+
 public fun name(nft: &EmoNFT): &string::String {
     &nft.name
 }
@@ -25,22 +64,57 @@ public fun url(nft: &EmoNFT): &Url {
 }
 
 
-#[allow(lint(self_transfer))]
+/// Mint an NFT and return it, the caller decides who receives it.
+/// Only the addresses in `minters` may mint.
+public fun mint(
+    minters: &Minters,
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>,
+    ctx: &mut TxContext
+): EmoNFT {
+    assert!(minters.is_minter(ctx.sender()), ENotMinter);
+    new(name, description, url, ctx)
+}
+
+/// Kept so the package stays upgrade compatible. It cannot check the allowlist
+/// and always aborts, use `mint_to_sender_checked`
 public fun mint_to_sender(
+    _name: vector<u8>,
+    _description: vector<u8>,
+    _url: vector<u8>,
+    _ctx: &mut TxContext
+) {
+    abort EDeprecated
+}
+
+/// Mint an NFT to the sender, who must be in `minters`
+#[allow(lint(self_transfer))]
+public fun mint_to_sender_checked(
+    minters: &Minters,
     name: vector<u8>,
     description: vector<u8>,
     url: vector<u8>,
     ctx: &mut TxContext
 ) {
-    let sender = ctx.sender();
-    let nft = EmoNFT {
+    let nft = mint(minters, name, description, url, ctx);
+    transfer::public_transfer(nft, ctx.sender());
+}
+
+/// Mints without checking the sender, for modules of the package that authorize
+/// the mint themselves, e.g. `claim` with the oracle's signature
+public(package) fun new(
+    name: vector<u8>,
+    description: vector<u8>,
+    url: vector<u8>,
+    ctx: &mut TxContext
+): EmoNFT {
+    EmoNFT {
         id: object::new(ctx),
         name: string::utf8(name),
         description: string::utf8(description),
         url: url::new_unsafe_from_bytes(url)
-    };
-
-    transfer::public_transfer(nft, sender);
+    }
 }
 
 public fun transfer(nft: EmoNFT, recipient: address, _ctx: &mut TxContext) {
@@ -58,4 +132,9 @@ public fun update_description(
 public fun burn(nft: EmoNFT, _ctx: &mut TxContext) {
     let EmoNFT { id, name: _, description: _, url: _ } = nft;
     id.delete();
-}
\ No newline at end of file
+}
+
+#[test_only]
+public fun init_for_testing(ctx: &mut TxContext) {
+    init(ctx);
+}
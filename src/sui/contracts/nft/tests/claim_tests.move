@@ -0,0 +1,113 @@
+#[test_only]
+module nft::claim_tests;
+
+use nft::claim::{Self, ClaimRegistry};
+use sui::clock::{Self, Clock};
+
+const ORACLE: address = @0xA;
+const VIEWER: address = @0xb0b;
+
+/// Public key of the ed25519 test key whose seed is 32 bytes of 0x01.
+const ORACLE_PUBLIC_KEY: vector<u8> = x"8a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b40f6f5c";
+/// First object created with a zero transaction hash: blake2b256(0xf1 || tx hash || 0u64).
+const REGISTRY: address = @0x034401905bebdf8c04f3cd5f04f442a39372c8dc321c29edfb4f9cb30b23ab96;
+/// Signature of the test key over the `Attestation` that `claim` redeems at `REGISTRY`.
+const SIGNATURE: vector<u8> = x"ccbc1a3b0fa1f1487e59957c9d23bc50c9d0a68f6644c9a3289413f94c212dbd44d992672e47fabc99329912cf5fba205b8e32f67006589c293ba67683611a0f";
+/// BCS of `attestation` at registry 0xc1a1, also checked by the oracle's `Attestation` tests.
+const ATTESTATION_BCS: vector<u8> = x"000000000000000000000000000000000000000000000000000000000000c1a14478760800000000611b81280000000004636861740068e5cf8b0100000000000000000000000000000000000000000000000000000000000000000b0b0700000000000000c08feecf8b01000013616e6469736b65792073747265616d204e465426436c61696d656420696e20746865205477697463682063686174206f6620616e6469736b65791e68747470733a2f2f7777772e7477697463682e74762f616e6469736b6579";
+
+const NONCE: u64 = 7;
+const TIMESTAMP_MS: u64 = 1700000000000;
+const EXPIRES_AT_MS: u64 = 1700000600000;
+
+fun setup(public_key: vector<u8>): (TxContext, ClaimRegistry, Clock) {
+    let mut ctx = tx_context::new(ORACLE, x"0000000000000000000000000000000000000000000000000000000000000000", 0, 0, 0);
+    let registry = claim::new_registry_for_testing(public_key, &mut ctx);
+    assert!(object::id(&registry) == object::id_from_address(REGISTRY));
+    let mut clock = clock::create_for_testing(&mut ctx);
+    clock.set_for_testing(TIMESTAMP_MS);
+    (ctx, registry, clock)
+}
+
+fun redeem(registry: &mut ClaimRegistry, signature: vector<u8>, clock: &Clock, ctx: &mut TxContext) {
+    claim::claim_for_testing(
+        registry,
+        141981764,
+        679549793,
+        b"chat",
+        TIMESTAMP_MS,
+        VIEWER,
+        NONCE,
+        EXPIRES_AT_MS,
+        b"andiskey stream NFT",
+        b"Claimed in the Twitch chat of andiskey",
+        b"https://www.twitch.tv/andiskey",
+        signature,
+        clock,
+        ctx
+    );
+}
+
+fun teardown(registry: ClaimRegistry, clock: Clock) {
+    claim::destroy_registry_for_testing(registry);
+    clock.destroy_for_testing();
+}
+
+#[test]
+fun test_attestation_layout() {
+    let bytes = claim::attestation_bytes_for_testing(
+        object::id_from_address(@0xc1a1),
+        141981764,
+        679549793,
+        b"chat",
+        TIMESTAMP_MS,
+        VIEWER,
+        NONCE,
+        EXPIRES_AT_MS,
+        b"andiskey stream NFT",
+        b"Claimed in the Twitch chat of andiskey",
+        b"https://www.twitch.tv/andiskey"
+    );
+    assert!(bytes == ATTESTATION_BCS);
+}
+
+#[test]
+fun test_claim() {
+    let (mut ctx, mut registry, clock) = setup(ORACLE_PUBLIC_KEY);
+    redeem(&mut registry, SIGNATURE, &clock, &mut ctx);
+    assert!(registry.is_nonce_used(NONCE));
+    teardown(registry, clock);
+}
+
+#[test, expected_failure(abort_code = ::nft::claim::ENonceUsed)]
+fun test_reused_nonce() {
+    let (mut ctx, mut registry, clock) = setup(ORACLE_PUBLIC_KEY);
+    redeem(&mut registry, SIGNATURE, &clock, &mut ctx);
+    redeem(&mut registry, SIGNATURE, &clock, &mut ctx);
+    teardown(registry, clock);
+}
+
+#[test, expected_failure(abort_code = ::nft::claim::EInvalidSignature)]
+fun test_bad_signature() {
+    let (mut ctx, mut registry, clock) = setup(ORACLE_PUBLIC_KEY);
+    let mut signature = SIGNATURE;
+    let first = &mut signature[0];
+    *first = *first ^ 1;
+    redeem(&mut registry, signature, &clock, &mut ctx);
+    teardown(registry, clock);
+}
+
+#[test, expected_failure(abort_code = ::nft::claim::EExpired)]
+fun test_expired() {
+    let (mut ctx, mut registry, mut clock) = setup(ORACLE_PUBLIC_KEY);
+    clock.set_for_testing(EXPIRES_AT_MS + 1);
+    redeem(&mut registry, SIGNATURE, &clock, &mut ctx);
+    teardown(registry, clock);
+}
+
+#[test, expected_failure(abort_code = ::nft::claim::EOracleKeyNotSet)]
+fun test_oracle_key_not_set() {
+    let (mut ctx, mut registry, clock) = setup(vector[]);
+    redeem(&mut registry, SIGNATURE, &clock, &mut ctx);
+    teardown(registry, clock);
+}
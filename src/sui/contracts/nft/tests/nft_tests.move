@@ -1,17 +1,70 @@
 #[test_only]
 module nft::nft_tests;
-// uncomment this line to import the module
-use kiosk::kiosk;
-use sui::tx_context::{TxContext, new_tx_context};
 
-const ENotImplemented: u64 = 0;
+use nft::nft::{Self, EmoNFT, MintAdminCap, Minters};
+use sui::test_scenario;
+
+const ORACLE: address = @0xA;
+const VIEWER: address = @0xB;
 
 #[test]
-fun test_nft() {
-    // pass
+fun test_mint() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    nft::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    {
+        let minters = scenario.take_shared<Minters>();
+        assert!(nft::is_minter(&minters, ORACLE));
+        let nft = nft::mint(&minters, b"name", b"description", b"https://www.twitch.tv", scenario.ctx());
+        transfer::public_transfer(nft, VIEWER);
+        test_scenario::return_shared(minters);
+    };
+    scenario.next_tx(VIEWER);
+    {
+        let nft = scenario.take_from_sender<EmoNFT>();
+        assert!(nft::name(&nft) == b"name".to_string());
+        scenario.return_to_sender(nft);
+    };
+    scenario.end();
+}
+
+#[test, expected_failure(abort_code = ::nft::nft::ENotMinter)]
+fun test_mint_not_minter() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    nft::init_for_testing(scenario.ctx());
+    scenario.next_tx(VIEWER);
+    let minters = scenario.take_shared<Minters>();
+    nft::mint_to_sender_checked(&minters, b"name", b"description", b"https://www.twitch.tv", scenario.ctx());
+    test_scenario::return_shared(minters);
+    scenario.end();
+}
+
+#[test, expected_failure(abort_code = ::nft::nft::ENotMinter)]
+fun test_removed_minter() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    nft::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    {
+        let cap = scenario.take_from_sender<MintAdminCap>();
+        let mut minters = scenario.take_shared<Minters>();
+        nft::add_minter(&cap, &mut minters, VIEWER);
+        assert!(nft::is_minter(&minters, VIEWER));
+        nft::remove_minter(&cap, &mut minters, ORACLE);
+        test_scenario::return_shared(minters);
+        scenario.return_to_sender(cap);
+    };
+    scenario.next_tx(ORACLE);
+    let minters = scenario.take_shared<Minters>();
+    nft::mint_to_sender_checked(&minters, b"name", b"description", b"https://www.twitch.tv", scenario.ctx());
+    test_scenario::return_shared(minters);
+    scenario.end();
 }
 
-#[test, expected_failure(abort_code = ::nft::nft_tests::ENotImplemented)]
-fun test_nft_fail() {
-    abort ENotImplemented
+#[test, expected_failure(abort_code = ::nft::nft::EDeprecated)]
+fun test_unchecked_mint_aborts() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    nft::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    nft::mint_to_sender(b"name", b"description", b"https://www.twitch.tv", scenario.ctx());
+    scenario.end();
 }
@@ -0,0 +1,109 @@
+module stream_feed::stream_feed;
+
+use std::string::{Self, String};
+use sui::clock::Clock;
+use sui::event;
+
+/// Allows creating and updating feeds, held by the oracle.
+public struct FeedAdminCap has key, store {
+    id: UID
+}
+
+/// Latest published stats of a Twitch channel, readable by any contract.
+public struct StreamFeed has key {
+    id: UID,
+    broadcaster_id: u64,
+    login: String,
+    live: bool,
+    viewer_count: u64,
+    game_id: String,
+    game_name: String,
+    /// Clock time of the last update
+    updated_at_ms: u64,
+    updates: u64
+}
+
+public struct FeedUpdated has copy, drop {
+    feed: ID,
+    broadcaster_id: u64,
+    live: bool,
+    viewer_count: u64,
+    game_id: String
+}
+
+fun init(ctx: &mut TxContext) {
+    transfer::transfer(FeedAdminCap { id: object::new(ctx) }, ctx.sender());
+}
+
+/// Shares an empty feed of a channel, the oracle is configured with its id.
+public fun create_feed(
+    _: &FeedAdminCap,
+    broadcaster_id: u64,
+    login: vector<u8>,
+    ctx: &mut TxContext
+) {
+    transfer::share_object(StreamFeed {
+        id: object::new(ctx),
+        broadcaster_id,
+        login: string::utf8(login),
+        live: false,
+        viewer_count: 0,
+        game_id: string::utf8(b""),
+        game_name: string::utf8(b""),
+        updated_at_ms: 0,
+        updates: 0
+    });
+}
+
+public fun update(
+    _: &FeedAdminCap,
+    feed: &mut StreamFeed,
+    live: bool,
+    viewer_count: u64,
+    game_id: vector<u8>,
+    game_name: vector<u8>,
+    clock: &Clock
+) {
+    feed.live = live;
+    feed.viewer_count = viewer_count;
+    feed.game_id = string::utf8(game_id);
+    feed.game_name = string::utf8(game_name);
+    feed.updated_at_ms = clock.timestamp_ms();
+    feed.updates = feed.updates + 1;
+    event::emit(FeedUpdated {
+        feed: object::id(feed),
+        broadcaster_id: feed.broadcaster_id,
+        live,
+        viewer_count,
+        game_id: feed.game_id
+    });
+}
+
+public fun broadcaster_id(feed: &StreamFeed): u64 {
+    feed.broadcaster_id
+}
+
+public fun live(feed: &StreamFeed): bool {
+    feed.live
+}
+
+public fun viewer_count(feed: &StreamFeed): u64 {
+    feed.viewer_count
+}
+
+public fun game_id(feed: &StreamFeed): &String {
+    &feed.game_id
+}
+
+public fun game_name(feed: &StreamFeed): &String {
+    &feed.game_name
+}
+
+public fun updated_at_ms(feed: &StreamFeed): u64 {
+    feed.updated_at_ms
+}
+
+#[test_only]
+public fun init_for_testing(ctx: &mut TxContext) {
+    init(ctx);
+}
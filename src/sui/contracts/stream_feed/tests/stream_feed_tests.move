@@ -0,0 +1,35 @@
+#[test_only]
+module stream_feed::stream_feed_tests;
+
+use stream_feed::stream_feed::{Self, FeedAdminCap, StreamFeed};
+use sui::clock;
+use sui::test_scenario;
+
+const ORACLE: address = @0xA;
+
+#[test]
+fun test_update_feed() {
+    let mut scenario = test_scenario::begin(ORACLE);
+    stream_feed::init_for_testing(scenario.ctx());
+    scenario.next_tx(ORACLE);
+    {
+        let cap = scenario.take_from_sender<FeedAdminCap>();
+        stream_feed::create_feed(&cap, 42, b"andiskey", scenario.ctx());
+        scenario.return_to_sender(cap);
+    };
+    scenario.next_tx(ORACLE);
+    {
+        let cap = scenario.take_from_sender<FeedAdminCap>();
+        let mut feed = scenario.take_shared<StreamFeed>();
+        let mut clock = clock::create_for_testing(scenario.ctx());
+        clock.set_for_testing(1000);
+        stream_feed::update(&cap, &mut feed, true, 120, b"509658", b"Just Chatting", &clock);
+        assert!(stream_feed::live(&feed));
+        assert!(stream_feed::viewer_count(&feed) == 120);
+        assert!(stream_feed::updated_at_ms(&feed) == 1000);
+        clock.destroy_for_testing();
+        test_scenario::return_shared(feed);
+        scenario.return_to_sender(cap);
+    };
+    scenario.end();
+}
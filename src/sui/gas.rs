@@ -0,0 +1,448 @@
+use crate::config::env_or;
+use crate::sui::Oracle;
+use crate::sui::journal::JournalSubject;
+use crate::sui::outcome::{ExecutionError, ExecutionOutcome, called_packages, simulated_response};
+use anyhow::anyhow;
+use futures::StreamExt;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sui_sdk::rpc_types::{
+    Coin, DryRunTransactionBlockResponse, SuiExecutionStatus, SuiTransactionBlockEffectsAPI,
+    SuiTransactionBlockResponse,
+};
+use sui_sdk::types::base_types::{ObjectID, ObjectRef};
+use sui_sdk::types::digests::TransactionDigest;
+use sui_sdk::types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+use sui_sdk::types::transaction::{
+    Argument, Command, ObjectArg, ProgrammableTransaction, TransactionData,
+};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+const DEFAULT_GAS_BUDGET_CAP: u64 = 10_000_000;
+const DEFAULT_GAS_BUDGET_MARGIN_PCT: u64 = 20;
+const DEFAULT_GAS_COINS: usize = 4;
+const DEFAULT_GAS_MAINTENANCE_SECS: u64 = 600;
+/// Dust coins merged by a single transaction, well below the input object limit.
+const MAX_MERGE_COINS: usize = 256;
+
+/// How the oracle keeps its SUI coins usable as gas.
+#[derive(Debug, Clone, Copy)]
+pub struct GasConfig {
+    /// Most a transaction may spend, `GAS_BUDGET_CAP` env. Transactions estimated
+    /// above it are not submitted and every gas coin has to cover it.
+    pub budget_cap: u64,
+    /// Added on top of the dry-run cost, `GAS_BUDGET_MARGIN_PCT` env.
+    pub budget_margin_pct: u64,
+    /// Gas coins kept around so that transactions do not lock the same coin, `GAS_COINS` env.
+    pub coin_count: usize,
+    /// Balance of each coin split off the largest one, `GAS_COIN_BALANCE` env.
+    pub coin_balance: u64,
+    /// Coins below this balance are merged into the largest one, `GAS_DUST_BALANCE` env.
+    pub dust_balance: u64,
+}
+
+impl GasConfig {
+    pub fn from_env() -> Self {
+        let budget_cap = env_or("GAS_BUDGET_CAP", DEFAULT_GAS_BUDGET_CAP);
+        Self {
+            budget_cap,
+            budget_margin_pct: env_or("GAS_BUDGET_MARGIN_PCT", DEFAULT_GAS_BUDGET_MARGIN_PCT),
+            coin_count: env_or("GAS_COINS", DEFAULT_GAS_COINS),
+            coin_balance: env_or("GAS_COIN_BALANCE", 5 * budget_cap),
+            dust_balance: env_or("GAS_DUST_BALANCE", budget_cap),
+        }
+    }
+}
+
+/// A coin of the oracle that pays for one transaction at a time.
+#[derive(Debug, Clone, Copy)]
+pub struct GasCoin {
+    pub object_ref: ObjectRef,
+    pub balance: u64,
+}
+
+impl GasCoin {
+    /// The coin after paying for `response`, with the version from its effects.
+    fn after(&self, response: &SuiTransactionBlockResponse) -> Option<Self> {
+        let effects = response.effects.as_ref()?;
+        let spent = effects.gas_cost_summary().net_gas_usage();
+        Some(Self {
+            object_ref: effects.gas_object().reference.to_object_ref(),
+            balance: self.balance.checked_add_signed(-spent)?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    idle: Vec<GasCoin>,
+    in_use: HashSet<ObjectID>,
+    /// Coins of transactions that may still execute with their current version,
+    /// kept out of the pool until the journal resolves the transaction.
+    quarantined: HashSet<ObjectID>,
+}
+
+impl PoolState {
+    fn is_leased(&self, id: &ObjectID) -> bool {
+        self.in_use.contains(id) || self.quarantined.contains(id)
+    }
+}
+
+/// Gas coins that are checked out by a transaction and returned with their new
+/// version, so concurrent transactions never lock the same coin.
+#[derive(Debug, Default)]
+pub struct GasPool {
+    state: Mutex<PoolState>,
+    returned: Notify,
+}
+
+impl GasPool {
+    /// Replaces the idle coins with the ones on chain that cover `budget`,
+    /// coins that are checked out are left alone.
+    fn refill(&self, coins: &[Coin], budget: u64) {
+        let mut state = self.state.lock().unwrap();
+        let idle = coins
+            .iter()
+            .filter(|coin| coin.balance >= budget && !state.is_leased(&coin.coin_object_id))
+            .map(|coin| GasCoin {
+                object_ref: coin.object_ref(),
+                balance: coin.balance,
+            })
+            .collect();
+        state.idle = idle;
+        drop(state);
+        self.returned.notify_waiters();
+    }
+
+    /// Checks out the smallest idle coin that covers `budget`, so large coins stay free for splitting.
+    fn try_checkout(&self, budget: u64) -> Option<GasCoin> {
+        let mut state = self.state.lock().unwrap();
+        let (index, _) = state
+            .idle
+            .iter()
+            .enumerate()
+            .filter(|(_, coin)| coin.balance >= budget)
+            .min_by_key(|(_, coin)| coin.balance)?;
+        let coin = state.idle.swap_remove(index);
+        state.in_use.insert(coin.object_ref.0);
+        Some(coin)
+    }
+
+    /// Checks out a specific coin unless it is leased, e.g. the largest one for
+    /// merging and splitting.
+    fn reserve(&self, id: &ObjectID) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.is_leased(id) {
+            return false;
+        }
+        state.idle.retain(|coin| &coin.object_ref.0 != id);
+        state.in_use.insert(*id);
+        true
+    }
+
+    fn give_back(&self, coin: GasCoin) {
+        let mut state = self.state.lock().unwrap();
+        state.in_use.remove(&coin.object_ref.0);
+        state.idle.push(coin);
+        drop(state);
+        self.returned.notify_one();
+    }
+
+    /// Drops a checked out coin whose version is unknown, the next refill reads it from chain.
+    fn forget(&self, id: &ObjectID) {
+        self.state.lock().unwrap().in_use.remove(id);
+        self.returned.notify_one();
+    }
+
+    /// Keeps `ids` out of the pool, checked out or not, until they are released.
+    pub(crate) fn quarantine(&self, ids: &[ObjectID]) {
+        let mut state = self.state.lock().unwrap();
+        for id in ids {
+            state.in_use.remove(id);
+            state.idle.retain(|coin| &coin.object_ref.0 != id);
+            state.quarantined.insert(*id);
+        }
+        drop(state);
+        self.returned.notify_one();
+    }
+
+    /// Lets quarantined `ids` back in with the next refill, which reads their version.
+    pub(crate) fn release(&self, ids: &[ObjectID]) {
+        let mut state = self.state.lock().unwrap();
+        for id in ids {
+            state.quarantined.remove(id);
+        }
+    }
+
+    fn in_use(&self) -> usize {
+        self.state.lock().unwrap().in_use.len()
+    }
+}
+
+impl Oracle {
+    /// Every SUI coin of the oracle, across all pages.
+    pub async fn gas_coins(&self) -> Result<Vec<Coin>, anyhow::Error> {
+        let coins = self
+            .client
+            .coin_read_api()
+            .get_coins_stream(self.address, None)
+            .collect()
+            .await;
+        Ok(coins)
+    }
+
+    /// Reloads the idle coins of the gas pool from chain.
+    pub async fn refill_gas_pool(&self) -> Result<(), anyhow::Error> {
+        let coins = self.gas_coins().await?;
+        self.gas_pool.refill(&coins, self.gas.budget_cap);
+        Ok(())
+    }
+
+    /// Waits for an idle coin that covers `budget`. The pool is loaded from chain
+    /// when no coin is checked out that could still be returned.
+    pub(crate) async fn checkout_gas(&self, budget: u64) -> Result<GasCoin, anyhow::Error> {
+        loop {
+            let returned = self.gas_pool.returned.notified();
+            if let Some(coin) = self.gas_pool.try_checkout(budget) {
+                return Ok(coin);
+            }
+            if self.gas_pool.in_use() == 0 {
+                self.refill_gas_pool().await?;
+                return self.gas_pool.try_checkout(budget).ok_or(anyhow!(
+                    "Oracle {} has no coin with {} MIST for gas",
+                    self.address,
+                    budget
+                ));
+            }
+            returned.await;
+        }
+    }
+
+    /// Returns `coin` to the pool with the version it has after `response`.
+    /// A dry run leaves the coin untouched.
+    pub(crate) fn return_gas(
+        &self,
+        coin: &GasCoin,
+        response: Result<&SuiTransactionBlockResponse, &anyhow::Error>,
+    ) {
+        if self.dry_run {
+            self.gas_pool.give_back(*coin);
+            return;
+        }
+        match response.ok().and_then(|response| coin.after(response)) {
+            Some(coin) => self.gas_pool.give_back(coin),
+            None => self.forget_gas(&coin.object_ref.0, response),
+        }
+    }
+
+    /// Drops a checked out coin whose version is unknown after `response`. When the
+    /// transaction is unconfirmed it may still execute with the coin's old version,
+    /// so the coin is quarantined until the journal resolves the transaction.
+    fn forget_gas(
+        &self,
+        id: &ObjectID,
+        response: Result<&SuiTransactionBlockResponse, &anyhow::Error>,
+    ) {
+        match response {
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<ExecutionError>(),
+                    Some(ExecutionError::Unconfirmed { .. })
+                ) =>
+            {
+                warn!(
+                    "Quarantining gas coin {} until its transaction is resolved",
+                    id
+                );
+                self.gas_pool.quarantine(&[*id]);
+            }
+            _ => self.gas_pool.forget(id),
+        }
+    }
+
+    /// Dry-runs `pt` paid with `gas` and returns its computation and storage cost
+    /// plus the margin, along with the dry run. Fails when the dry run fails, whose
+    /// simulated outcome is recorded, or the estimate exceeds the cap.
+    pub(crate) async fn estimate_budget(
+        &self,
+        pt: &ProgrammableTransaction,
+        gas: ObjectRef,
+        gas_price: u64,
+    ) -> Result<(u64, DryRunTransactionBlockResponse), anyhow::Error> {
+        let tx_data = TransactionData::new_programmable(
+            self.address,
+            vec![gas],
+            pt.clone(),
+            self.gas.budget_cap,
+            gas_price,
+        );
+        let dry_run = self
+            .client
+            .read_api()
+            .dry_run_transaction_block(tx_data)
+            .await?;
+        if let SuiExecutionStatus::Failure { .. } = dry_run.effects.status() {
+            // recorded like an executed failure, the abort typed the same way
+            let outcome = ExecutionOutcome::from_response(
+                &simulated_response(dry_run.clone()),
+                true,
+                &called_packages(pt),
+            )?;
+            self.record_outcome(&outcome).await;
+            if let Err(e) = outcome.into_result() {
+                return Err(e.into());
+            }
+        }
+        let cost = dry_run.effects.gas_cost_summary();
+        let estimate =
+            (cost.computation_cost + cost.storage_cost) * (100 + self.gas.budget_margin_pct) / 100;
+        if estimate > self.gas.budget_cap {
+            return Err(ExecutionError::OverBudget {
+                estimate,
+                cap: self.gas.budget_cap,
+            }
+            .into());
+        }
+        Ok((estimate, dry_run))
+    }
+
+    /// Merges the coins below the dust balance into the largest coin, which pays the gas.
+    /// Coins that are checked out or quarantined are left alone.
+    pub async fn merge_dust(&self) -> Result<Option<TransactionDigest>, anyhow::Error> {
+        let mut coins = self.gas_coins().await?;
+        coins.sort_by_key(|coin| std::cmp::Reverse(coin.balance));
+        let Some((largest, rest)) = coins.split_first() else {
+            return Ok(None);
+        };
+        if largest.balance < self.gas.budget_cap || !self.gas_pool.reserve(&largest.coin_object_id)
+        {
+            return Ok(None);
+        }
+        // dust that covers a budget can be in the pool too, reserving keeps it from being checked out
+        let dust: Vec<&Coin> = rest
+            .iter()
+            .filter(|coin| coin.balance < self.gas.dust_balance)
+            .filter(|coin| self.gas_pool.reserve(&coin.coin_object_id))
+            .take(MAX_MERGE_COINS)
+            .collect();
+        if dust.is_empty() {
+            self.gas_pool.give_back(GasCoin {
+                object_ref: largest.object_ref(),
+                balance: largest.balance,
+            });
+            return Ok(None);
+        }
+
+        let mut ptb = ProgrammableTransactionBuilder::new();
+        let dust_args = match dust
+            .iter()
+            .map(|coin| ptb.obj(ObjectArg::ImmOrOwnedObject(coin.object_ref())))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(dust_args) => dust_args,
+            Err(e) => {
+                for coin in std::iter::once(largest).chain(dust) {
+                    self.gas_pool.forget(&coin.coin_object_id);
+                }
+                return Err(e.into());
+            }
+        };
+        ptb.command(Command::MergeCoins(Argument::GasCoin, dust_args));
+        info!(
+            "Merging {} dust coin(s) into {}",
+            dust.len(),
+            largest.coin_object_id
+        );
+        let response = self
+            .execute_with_gas(ptb.finish(), largest.object_ref(), &JournalSubject::Gas)
+            .await;
+        // the coin's new ref is picked up by the refill after maintenance
+        for coin in std::iter::once(largest).chain(dust) {
+            self.forget_gas(&coin.coin_object_id, response.as_ref());
+        }
+        Ok(Some(
+            self.outcome_of(&response?, &JournalSubject::Gas, &[])
+                .await?
+                .digest,
+        ))
+    }
+
+    /// Splits the largest coin until the oracle holds `coin_count` coins of
+    /// at least `coin_balance`, keeping enough on it to pay for the split.
+    pub async fn split_gas(&self) -> Result<Option<TransactionDigest>, anyhow::Error> {
+        let mut coins = self.gas_coins().await?;
+        coins.sort_by_key(|coin| std::cmp::Reverse(coin.balance));
+        let Some(largest) = coins.first() else {
+            return Ok(None);
+        };
+        let usable = coins
+            .iter()
+            .filter(|coin| coin.balance >= self.gas.coin_balance)
+            .count();
+        let affordable = largest
+            .balance
+            .saturating_sub(self.gas.coin_balance + self.gas.budget_cap)
+            .checked_div(self.gas.coin_balance)
+            .unwrap_or(0) as usize;
+        let missing = self.gas.coin_count.saturating_sub(usable).min(affordable);
+        if missing == 0 || !self.gas_pool.reserve(&largest.coin_object_id) {
+            return Ok(None);
+        }
+
+        let mut ptb = ProgrammableTransactionBuilder::new();
+        ptb.pay_sui(
+            vec![self.address; missing],
+            vec![self.gas.coin_balance; missing],
+        )?;
+        info!(
+            "Splitting {} gas coin(s) of {} MIST off {}",
+            missing, self.gas.coin_balance, largest.coin_object_id
+        );
+        let response = self
+            .execute_with_gas(ptb.finish(), largest.object_ref(), &JournalSubject::Gas)
+            .await;
+        // the coin's new ref is picked up by the refill after maintenance
+        self.forget_gas(&largest.coin_object_id, response.as_ref());
+        Ok(Some(
+            self.outcome_of(&response?, &JournalSubject::Gas, &[])
+                .await?
+                .digest,
+        ))
+    }
+}
+
+/// Periodically merges the oracle's dust, splits gas coins for parallel transactions
+/// and reloads the gas pool with the result.
+pub struct GasManager {
+    oracle: Arc<Oracle>,
+}
+
+impl GasManager {
+    pub fn new(oracle: Arc<Oracle>) -> Self {
+        Self { oracle }
+    }
+
+    pub async fn run(self) {
+        let maintenance_secs = env_or("GAS_MAINTENANCE_SECS", DEFAULT_GAS_MAINTENANCE_SECS);
+        let mut interval = tokio::time::interval(Duration::from_secs(maintenance_secs));
+        loop {
+            interval.tick().await;
+            // merge first, the dust then adds to the coin that is split
+            match self.oracle.merge_dust().await {
+                Ok(Some(digest)) => info!("Merged dust coins: {}", digest),
+                Ok(None) => {}
+                Err(e) => error!("Failed to merge dust coins: {:?}", e),
+            }
+            match self.oracle.split_gas().await {
+                Ok(Some(digest)) => info!("Split gas coins: {}", digest),
+                Ok(None) => {}
+                Err(e) => error!("Failed to split gas coins: {:?}", e),
+            }
+            if let Err(e) = self.oracle.refill_gas_pool().await {
+                error!("Failed to refill the gas pool: {:?}", e);
+            }
+        }
+    }
+}
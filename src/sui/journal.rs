@@ -0,0 +1,521 @@
+use crate::GlobalError;
+use crate::chat_log::ChatBatch;
+use crate::claims::ClaimJob;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use crate::sui::Oracle;
+use crate::sui::outcome::{ExecutionError, ExecutionOutcome, called_packages};
+use crate::sui::stream_feed::FeedState;
+use anyhow::anyhow;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use sui_sdk::rpc_types::{
+    SuiObjectDataOptions, SuiTransactionBlockResponse, SuiTransactionBlockResponseOptions,
+};
+use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::types::digests::TransactionDigest;
+use sui_sdk::types::quorum_driver_types::ExecuteTransactionRequestType;
+use sui_sdk::types::transaction::{
+    InputObjectKind, Transaction, TransactionDataAPI, TransactionKind,
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const SUBMIT_ATTEMPTS: u32 = 3;
+const SUBMIT_RETRY_SECS: u64 = 2;
+/// Unsettled rows younger than this may still be settled by the worker that submitted them.
+const DEFAULT_JOURNAL_STALE_SECS: i64 = 10 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalStatus {
+    /// Signed and maybe submitted, whether it executed is unknown.
+    Pending,
+    /// The network returned its effects, successful or not.
+    Confirmed,
+    /// Its outcome is recorded in the rows of its subject.
+    Settled,
+    /// Unknown to the network and could not be resubmitted.
+    Dropped,
+}
+
+impl JournalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalStatus::Pending => "pending",
+            JournalStatus::Confirmed => "confirmed",
+            JournalStatus::Settled => "settled",
+            JournalStatus::Dropped => "dropped",
+        }
+    }
+}
+
+/// What a journaled transaction was built for, its outcome is recorded there when it is settled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalSubject {
+    /// Mints the claims to their recipients, in the order of its mints.
+    Mint {
+        claim_ids: Vec<Uuid>,
+        recipients: Vec<SuiAddress>,
+    },
+    /// Publishes the root of a chat batch.
+    ChatBatch { batch_id: Uuid },
+    /// Writes the stream stats of a channel to its feed.
+    StreamFeed {
+        channel_login: String,
+        feed: ObjectID,
+        state: FeedState,
+    },
+    /// Merges or splits the oracle's gas coins, there is nothing to record.
+    Gas,
+}
+
+impl JournalSubject {
+    pub fn claim_ids(&self) -> &[Uuid] {
+        match self {
+            JournalSubject::Mint { claim_ids, .. } => claim_ids,
+            _ => &[],
+        }
+    }
+
+    /// Records the successful `outcome` in the rows the transaction was built for.
+    /// A dry run leaves claims and batches as they are, its outcome is only in
+    /// `transaction_outcomes`.
+    async fn record(
+        &self,
+        transaction: &deadpool_postgres::Transaction<'_>,
+        outcome: &ExecutionOutcome,
+    ) -> Result<(), GlobalError> {
+        if outcome.simulated && !matches!(self, JournalSubject::StreamFeed { .. }) {
+            return Ok(());
+        }
+        let digest = outcome.digest.to_string();
+        match self {
+            JournalSubject::Mint {
+                claim_ids,
+                recipients,
+            } => {
+                let effects = serde_json::to_value(outcome)?;
+                let nfts = outcome.created_for(recipients);
+                for (id, nft) in claim_ids.iter().zip(nfts) {
+                    ClaimJob::mark_minted(
+                        transaction,
+                        *id,
+                        &digest,
+                        nft.map(|id| id.to_string()).as_deref(),
+                        Some(&effects),
+                    )
+                    .await?;
+                }
+            }
+            JournalSubject::ChatBatch { batch_id } => {
+                let object = outcome
+                    .created
+                    .first()
+                    .ok_or(anyhow!("Transaction {} created no batch", digest))?;
+                ChatBatch::mark_published(
+                    transaction,
+                    *batch_id,
+                    &digest,
+                    &object.object_id.to_string(),
+                )
+                .await?;
+            }
+            JournalSubject::StreamFeed {
+                channel_login,
+                feed,
+                state,
+            } => {
+                state
+                    .record(transaction, channel_login, feed, &digest, outcome.simulated)
+                    .await?;
+            }
+            JournalSubject::Gas => {}
+        }
+        Ok(())
+    }
+}
+
+/// A signed transaction, recorded before it is submitted so that it can be
+/// looked up or resubmitted after a crash.
+#[derive(Debug, Clone)]
+pub struct JournaledTransaction {
+    pub digest: String,
+    /// BCS of the signed `Transaction`, resubmitting it cannot execute it twice.
+    pub transaction: Vec<u8>,
+    pub subject: JournalSubject,
+    pub status: JournalStatus,
+}
+
+impl JournaledTransaction {
+    async fn insert(&self, client: &PgClient) -> Result<(), GlobalError> {
+        client
+            .execute(
+                "INSERT INTO transaction_journal (digest, transaction, claim_ids, subject, status) \
+                 VALUES ($1, $2, $3, $4, $5) ON CONFLICT (digest) DO NOTHING",
+                &[
+                    &self.digest,
+                    &self.transaction,
+                    &self.subject.claim_ids(),
+                    &serde_json::to_value(&self.subject)?,
+                    &self.status.as_str(),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Pending and confirmed transactions journaled at least `min_age_secs` ago, oldest first.
+    async fn unsettled(client: &PgClient, min_age_secs: i64) -> Result<Vec<Self>, GlobalError> {
+        let rows = client
+            .query(
+                "SELECT digest, transaction, subject, status FROM transaction_journal \
+                 WHERE status IN ($1, $2) AND created_at <= current_timestamp - make_interval(secs => $3) \
+                 ORDER BY created_at",
+                &[
+                    &JournalStatus::Pending.as_str(),
+                    &JournalStatus::Confirmed.as_str(),
+                    &(min_age_secs as f64),
+                ],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| -> Result<Self, GlobalError> {
+                let subject: serde_json::Value = row.get(2);
+                let status: String = row.get(3);
+                Ok(Self {
+                    digest: row.get(0),
+                    transaction: row.get(1),
+                    subject: serde_json::from_value(subject)?,
+                    status: if status == JournalStatus::Confirmed.as_str() {
+                        JournalStatus::Confirmed
+                    } else {
+                        JournalStatus::Pending
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Unsettled rows only, a settled or dropped transaction stays so.
+    async fn mark(
+        client: &PgClient,
+        digest: &str,
+        status: JournalStatus,
+        error: Option<&str>,
+    ) -> Result<(), GlobalError> {
+        client
+            .execute(
+                "UPDATE transaction_journal SET status = $2, error = $3 \
+                 WHERE digest = $1 AND status IN ('pending', 'confirmed')",
+                &[&digest, &status.as_str(), &error],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl Oracle {
+    /// Records `transaction` as pending, it must not be submitted when this fails.
+    pub(crate) async fn journal_pending(
+        &self,
+        transaction: &Transaction,
+        subject: &JournalSubject,
+    ) -> Result<(), anyhow::Error> {
+        let Some(pool) = &self.journal else {
+            return Ok(());
+        };
+        let journaled = JournaledTransaction {
+            digest: transaction.digest().to_string(),
+            transaction: bcs::to_bytes(transaction)?,
+            subject: subject.clone(),
+            status: JournalStatus::Pending,
+        };
+        let client = pool.get().await?;
+        journaled.insert(&client).await.map_err(|e| anyhow!(e))
+    }
+
+    /// Records the successful `outcome` for `subject` and marks its transaction settled,
+    /// in one database transaction. Without a journal nothing is recorded.
+    pub(crate) async fn settle(
+        &self,
+        subject: &JournalSubject,
+        outcome: &ExecutionOutcome,
+    ) -> Result<(), GlobalError> {
+        let Some(pool) = &self.journal else {
+            return Ok(());
+        };
+        let mut client = pool.get().await?;
+        let transaction = client.transaction().await?;
+        subject.record(&transaction, outcome).await?;
+        transaction
+            .execute(
+                "UPDATE transaction_journal SET status = $2, error = NULL \
+                 WHERE digest = $1 AND status IN ('pending', 'confirmed')",
+                &[
+                    &outcome.digest.to_string(),
+                    &JournalStatus::Settled.as_str(),
+                ],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Marks a journaled transaction, failures are logged and the row is resolved again later.
+    pub(crate) async fn journal_mark(
+        &self,
+        digest: &TransactionDigest,
+        status: JournalStatus,
+        error: Option<&str>,
+    ) {
+        let Some(pool) = &self.journal else {
+            return;
+        };
+        let marked = async {
+            let client = pool.get().await?;
+            JournaledTransaction::mark(&client, &digest.to_string(), status, error).await
+        };
+        if let Err(e) = marked.await {
+            error!(
+                "Failed to mark transaction {} as {}: {:?}",
+                digest,
+                status.as_str(),
+                e
+            );
+        }
+    }
+
+    /// Effects of an already executed transaction, `None` when the network does not know it.
+    async fn lookup(&self, digest: TransactionDigest) -> Option<SuiTransactionBlockResponse> {
+        self.client
+            .read_api()
+            .get_transaction_with_options(
+                digest,
+                SuiTransactionBlockResponseOptions::full_content().with_raw_effects(),
+            )
+            .await
+            .ok()
+    }
+
+    /// Whether `transaction` can no longer execute because a version of an owned
+    /// object it takes, e.g. its gas, was spent by another transaction.
+    async fn never_executes(&self, transaction: &Transaction) -> Result<bool, anyhow::Error> {
+        let inputs = transaction
+            .data()
+            .transaction_data()
+            .input_objects()
+            .map_err(|e| anyhow!(e))?;
+        for (id, version, _) in inputs.iter().filter_map(|input| match input {
+            InputObjectKind::ImmOrOwnedMoveObject(object_ref) => Some(object_ref),
+            _ => None,
+        }) {
+            let current = self
+                .client
+                .read_api()
+                .get_object_with_options(*id, SuiObjectDataOptions::new())
+                .await?
+                .object_ref_if_exists();
+            if current.is_none_or(|(_, current, _)| current != *version) {
+                // spent by this very transaction if it executed meanwhile
+                return Ok(self.lookup(*transaction.digest()).await.is_none());
+            }
+        }
+        Ok(false)
+    }
+
+    /// Submits the signed `transaction`. When the response is lost it is looked up
+    /// by digest and the identical bytes are resubmitted, which cannot execute twice.
+    pub(crate) async fn submit(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SuiTransactionBlockResponse, ExecutionError> {
+        let digest = *transaction.digest();
+        let mut last_error = String::new();
+        for attempt in 1..=SUBMIT_ATTEMPTS {
+            let submitted = self
+                .client
+                .quorum_driver_api()
+                .execute_transaction_block(
+                    transaction.clone(),
+                    SuiTransactionBlockResponseOptions::full_content().with_raw_effects(),
+                    Some(ExecuteTransactionRequestType::WaitForLocalExecution),
+                )
+                .await;
+            match submitted {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    warn!("Submitting {} failed, attempt {}: {}", digest, attempt, e);
+                    last_error = e.to_string();
+                }
+            }
+            if let Some(response) = self.lookup(digest).await {
+                return Ok(response);
+            }
+            tokio::time::sleep(Duration::from_secs(SUBMIT_RETRY_SECS)).await;
+        }
+        Err(ExecutionError::Unconfirmed {
+            digest,
+            error: last_error,
+        })
+    }
+
+    /// Settles the transactions that were not settled when the oracle stopped, then
+    /// requeues the claims that were taken but never submitted. Must finish before
+    /// anything submits transactions, which could otherwise repeat them.
+    pub async fn recover(&self) -> Result<(), anyhow::Error> {
+        let Some(pool) = &self.journal else {
+            return Ok(());
+        };
+        self.resolve_journal(0).await?;
+        let client = pool.get().await?;
+        let requeued = ClaimJob::requeue_unsubmitted(&client)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        if requeued > 0 {
+            info!("Requeued {} claim(s) that were never submitted", requeued);
+        }
+        Ok(())
+    }
+
+    /// Settles the unsettled transactions journaled at least `min_age_secs` ago.
+    /// Executed ones are settled with their outcome, unknown ones that mint claims
+    /// are resubmitted and the rest are dropped, their workers build them again.
+    /// Nothing is resubmitted in dry-run mode.
+    pub async fn resolve_journal(&self, min_age_secs: i64) -> Result<(), anyhow::Error> {
+        let Some(pool) = self.journal.as_ref().filter(|_| !self.dry_run) else {
+            return Ok(());
+        };
+        let unsettled = {
+            let client = pool.get().await?;
+            JournaledTransaction::unsettled(&client, min_age_secs)
+                .await
+                .map_err(|e| anyhow!(e))?
+        };
+        for journaled in unsettled {
+            // the coins stay out of the gas pool while the transaction may still execute
+            let coins = Self::input_objects(&journaled.transaction);
+            match self.resolve(pool, &journaled).await {
+                Ok(()) => self.gas_pool.release(&coins),
+                Err(e) => {
+                    self.gas_pool.quarantine(&coins);
+                    error!("Failed to settle transaction {}: {:?}", journaled.digest, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// IDs of the owned and shared objects a journaled transaction reads, its gas included.
+    fn input_objects(transaction: &[u8]) -> Vec<ObjectID> {
+        bcs::from_bytes::<Transaction>(transaction)
+            .ok()
+            .and_then(|transaction| transaction.data().transaction_data().input_objects().ok())
+            .into_iter()
+            .flatten()
+            .map(|input| input.object_id())
+            .collect()
+    }
+
+    /// Packages a journaled transaction calls, see [`called_packages`].
+    fn packages(transaction: &Transaction) -> Vec<ObjectID> {
+        match transaction.data().transaction_data().kind() {
+            TransactionKind::ProgrammableTransaction(pt) => called_packages(pt),
+            _ => vec![],
+        }
+    }
+
+    async fn resolve(
+        &self,
+        pool: &Pool,
+        journaled: &JournaledTransaction,
+    ) -> Result<(), anyhow::Error> {
+        let transaction: Transaction = bcs::from_bytes(&journaled.transaction)?;
+        let digest = *transaction.digest();
+        let claim_ids = journaled.subject.claim_ids();
+        let response = match self.lookup(digest).await {
+            Some(response) => Ok(response),
+            // it executed, the lookup is retried next time
+            None if journaled.status == JournalStatus::Confirmed => {
+                return Err(anyhow!("Confirmed transaction {} not found", digest));
+            }
+            None => {
+                info!("Resubmitting transaction {}", digest);
+                self.submit(&transaction).await.map_err(|e| anyhow!(e))
+            }
+        };
+        let failure = match response {
+            // settles it, or marks it settled when it failed
+            Ok(response) => match self
+                .outcome_of(&response, &journaled.subject, &Self::packages(&transaction))
+                .await
+            {
+                Ok(outcome) => {
+                    info!("Settled transaction {}", outcome.digest);
+                    return Ok(());
+                }
+                Err(e)
+                    if e.downcast_ref::<ExecutionError>()
+                        .is_some_and(ExecutionError::is_execution_failure) =>
+                {
+                    e
+                }
+                Err(e) => return Err(e),
+            },
+            Err(e) => {
+                // it may still execute until an object it takes is spent by another one,
+                // until then the row stays pending and is resolved next time
+                if !self.never_executes(&transaction).await? {
+                    return Err(e);
+                }
+                self.journal_mark(&digest, JournalStatus::Dropped, Some(&e.to_string()))
+                    .await;
+                e
+            }
+        };
+        // nothing was minted, the claims are minted again
+        if !claim_ids.is_empty() {
+            let client = pool.get().await?;
+            let jobs = ClaimJob::by_ids(&client, claim_ids)
+                .await
+                .map_err(|e| anyhow!(e))?;
+            ClaimJob::requeue(&client, &jobs, &failure.to_string())
+                .await
+                .map_err(|e| anyhow!(e))?;
+            warn!(
+                "Requeued {} claim(s) of transaction {}: {}",
+                jobs.len(),
+                digest,
+                failure
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Periodically settles journaled transactions that their worker left unsettled,
+/// e.g. because the submission was never confirmed or recording the outcome failed.
+pub struct JournalResolver {
+    oracle: Arc<Oracle>,
+}
+
+impl JournalResolver {
+    pub fn new(oracle: Arc<Oracle>) -> Self {
+        Self { oracle }
+    }
+
+    pub async fn run(self) {
+        let stale_secs = env_or("JOURNAL_STALE_SECS", DEFAULT_JOURNAL_STALE_SECS).max(1);
+        let mut interval = tokio::time::interval(Duration::from_secs(stale_secs as u64));
+        // startup recovery already ran
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.oracle.resolve_journal(stale_secs).await {
+                error!("Failed to resolve the transaction journal: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::GlobalError;
+use crate::claims::ClaimJob;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use crate::sui::attestation::Attestation;
+use crate::sui::outcome::ExecutionError;
+use crate::sui::{NftMetadata, NftMint, Oracle};
+use crate::twitch::channel_config::ChannelsConfig;
+use anyhow::anyhow;
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sui_sdk::types::base_types::ObjectID;
+use tracing::{error, info, warn};
+
+const DEFAULT_MINT_POLL_SECS: u64 = 5;
+const DEFAULT_MINT_WORKERS: usize = 4;
+const DEFAULT_MINT_BATCH_SIZE: i64 = 16;
+const DEFAULT_ATTESTATION_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// How claims are turned into NFTs, `CLAIM_MODE` env.
+#[derive(Debug, Clone, Copy)]
+pub enum ClaimMode {
+    /// The oracle mints and pays the gas, the default.
+    Mint,
+    /// The oracle signs an attestation that the viewer redeems at `registry`
+    /// with `nft::claim::claim`, paying their own gas.
+    Attest { registry: ObjectID, ttl_secs: u64 },
+}
+
+impl ClaimMode {
+    /// `CLAIM_MODE=attest` needs the shared `ClaimRegistry` in `CLAIM_REGISTRY`,
+    /// attestations expire after `ATTESTATION_TTL_SECS`.
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        match std::env::var("CLAIM_MODE").as_deref() {
+            Ok("attest") => {
+                let registry = std::env::var("CLAIM_REGISTRY")
+                    .map_err(|_| anyhow!("CLAIM_MODE=attest needs CLAIM_REGISTRY"))?;
+                Ok(ClaimMode::Attest {
+                    registry: ObjectID::from_hex_literal(&registry).map_err(|e| anyhow!(e))?,
+                    ttl_secs: env_or("ATTESTATION_TTL_SECS", DEFAULT_ATTESTATION_TTL_SECS),
+                })
+            }
+            Ok("mint") | Err(_) => Ok(ClaimMode::Mint),
+            Ok(mode) => Err(anyhow!("Unknown CLAIM_MODE {}", mode)),
+        }
+    }
+}
+
+/// Mints or attests queued claim jobs with several workers, skipping channels that are paused.
+pub struct Minter {
+    oracle: Arc<Oracle>,
+    pool: Pool,
+    channels: Arc<ChannelsConfig>,
+    mode: ClaimMode,
+}
+
+impl Minter {
+    pub fn new(
+        oracle: Arc<Oracle>,
+        pool: Pool,
+        channels: Arc<ChannelsConfig>,
+        mode: ClaimMode,
+    ) -> Self {
+        Self {
+            oracle,
+            pool,
+            channels,
+            mode,
+        }
+    }
+
+    /// Package, its `Minters` object and metadata of the NFT minted for `job`.
+    /// Metadata of the job's reward rule wins over the channel's.
+    fn nft_for(&self, job: &ClaimJob) -> Result<(ObjectID, ObjectID, NftMetadata), anyhow::Error> {
+        let channel_login = &job.channel_login;
+        let channel = self.channels.get(channel_login);
+        let (package, minters) = match channel.and_then(|c| c.nft_package.as_ref()) {
+            Some(package) => {
+                let minters = channel
+                    .and_then(|c| c.nft_minters.as_ref())
+                    .ok_or(anyhow!("Channel {} has no nft_minters", channel_login))?;
+                (
+                    ObjectID::from_hex_literal(package).map_err(|e| anyhow!(e))?,
+                    ObjectID::from_hex_literal(minters).map_err(|e| anyhow!(e))?,
+                )
+            }
+            None => (self.oracle.default_package(), self.oracle.default_minters()),
+        };
+        let metadata = NftMetadata {
+            name: job
+                .nft_name
+                .clone()
+                .or(channel.and_then(|c| c.nft_name.clone()))
+                .unwrap_or(format!("{} stream NFT", channel_login)),
+            description: job
+                .nft_description
+                .clone()
+                .or(channel.and_then(|c| c.nft_description.clone()))
+                .unwrap_or(format!("Claimed in the Twitch chat of {}", channel_login)),
+            url: job
+                .nft_url
+                .clone()
+                .or(channel.and_then(|c| c.nft_url.clone()))
+                .unwrap_or(format!("https://www.twitch.tv/{}", channel_login)),
+        };
+        Ok((package, minters, metadata))
+    }
+
+    /// The mint of `job`, fails when its channel's NFT is misconfigured.
+    fn nft_mint(&self, job: &ClaimJob) -> Result<NftMint, anyhow::Error> {
+        let (package, minters, metadata) = self.nft_for(job)?;
+        Ok(NftMint {
+            claim_id: job.id,
+            package,
+            minters,
+            recipient: job.recipient,
+            metadata,
+        })
+    }
+
+    /// Mints `batch` in one transaction, each claim gets the NFT its mint created
+    /// when the transaction is settled. The claims of a transaction that may have
+    /// executed stay processing until the journal is resolved, only errors before
+    /// or of the execution are returned.
+    async fn mint(&self, batch: &[ClaimJob]) -> Result<(), GlobalError> {
+        let mints = batch
+            .iter()
+            .map(|job| self.nft_mint(job))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let minted = self.oracle.mint_nfts(&mints).await;
+        if let Err(e) = &minted
+            && let Some(
+                ExecutionError::Unconfirmed { digest, .. } | ExecutionError::MissingEffects(digest),
+            ) = e.downcast_ref()
+        {
+            // the transaction may still execute, retrying could mint twice
+            warn!(
+                "Mint {} of {} claim(s) is not confirmed, it is resolved from the journal",
+                digest,
+                batch.len()
+            );
+            return Ok(());
+        }
+        let (outcome, _) = minted?;
+        if outcome.simulated {
+            // they stay processing, the next start returns them to the queue
+            info!(
+                "Dry run of {} claim(s) succeeded: {}",
+                batch.len(),
+                outcome.digest
+            );
+        } else {
+            info!("Minted {} claim(s): {}", batch.len(), outcome.digest);
+        }
+        Ok(())
+    }
+
+    /// Mints `jobs` in batches, a batch that fails to execute is split in halves and
+    /// retried until the claims that cannot be minted are isolated and marked as failed.
+    /// Any other error, e.g. of the RPC, requeues the jobs that are left as they are.
+    async fn mint_batches(
+        &self,
+        client: &PgClient,
+        jobs: Vec<ClaimJob>,
+    ) -> Result<(), GlobalError> {
+        let mut mintable = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            match self.nft_mint(&job) {
+                Ok(_) => mintable.push(job),
+                Err(e) => {
+                    error!("Failed to process claim {}: {:?}", job.id, e);
+                    job.mark_failed(client, &e.to_string()).await?;
+                }
+            }
+        }
+        if mintable.is_empty() {
+            return Ok(());
+        }
+        let mut batches = vec![mintable];
+        while let Some(mut batch) = batches.pop() {
+            let Err(e) = self.mint(&batch).await else {
+                continue;
+            };
+            let execution_failed = e
+                .downcast_ref::<ExecutionError>()
+                .is_some_and(ExecutionError::is_execution_failure);
+            if !execution_failed {
+                batches.push(batch);
+                let left: Vec<ClaimJob> = batches.into_iter().flatten().collect();
+                ClaimJob::requeue(client, &left, &e.to_string()).await?;
+                warn!("Requeued {} claim(s) after: {:?}", left.len(), e);
+                // the caller stops draining the queue until the next poll
+                return Err(e);
+            }
+            if batch.len() > 1 {
+                info!(
+                    "Failed to mint {} claim(s), splitting: {:?}",
+                    batch.len(),
+                    e
+                );
+                let half = batch.split_off(batch.len() / 2);
+                batches.push(half);
+                batches.push(batch);
+            } else if self.oracle.is_dry_run() {
+                // it stays processing, the next start returns it to the queue
+                warn!("Dry run of claim {} failed: {:?}", batch[0].id, e);
+            } else {
+                let job = &batch[0];
+                error!("Failed to process claim {}: {:?}", job.id, e);
+                job.mark_failed(client, &e.to_string()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn attest(
+        &self,
+        client: &PgClient,
+        job: &ClaimJob,
+        registry: ObjectID,
+        ttl_secs: u64,
+    ) -> Result<(), GlobalError> {
+        let (_, _, metadata) = self.nft_for(job)?;
+        let nonce = ClaimJob::next_nonce(client).await?;
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let attestation = Attestation {
+            registry,
+            twitch_user_id: job.user_id as u64,
+            channel_id: job.channel_id as u64,
+            event: job.event.clone().into_bytes(),
+            timestamp_ms: job.created_at_ms as u64,
+            recipient: job.recipient,
+            nonce: nonce as u64,
+            expires_at_ms: now_ms + ttl_secs * 1000,
+            name: metadata.name.into_bytes(),
+            description: metadata.description.into_bytes(),
+            url: metadata.url.into_bytes(),
+        };
+        let signed = self.oracle.attest(&attestation)?;
+        ClaimJob::mark_attested(
+            client,
+            job.id,
+            nonce,
+            &signed.payload,
+            &signed.signature,
+            &signed.public_key,
+        )
+        .await?;
+        info!(
+            "Attested claim {} to {} with nonce {}",
+            job.id, job.recipient, nonce
+        );
+        Ok(())
+    }
+
+    /// Mints or attests the next `batch_size` pending jobs, returns whether there were any.
+    async fn mint_next(&self, batch_size: i64) -> Result<bool, GlobalError> {
+        let client = self.pool.get().await?;
+        let jobs = ClaimJob::next_pending(&client, batch_size).await?;
+        if jobs.is_empty() {
+            return Ok(false);
+        }
+        match self.mode {
+            ClaimMode::Mint => self.mint_batches(&client, jobs).await?,
+            ClaimMode::Attest { registry, ttl_secs } => {
+                for job in jobs {
+                    if let Err(e) = self.attest(&client, &job, registry, ttl_secs).await {
+                        error!("Failed to process claim {}: {:?}", job.id, e);
+                        job.mark_failed(&client, &e.to_string()).await?;
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drains the queue every `poll_secs`, concurrently with the other workers.
+    async fn work(self: Arc<Self>, poll_secs: u64, batch_size: i64) {
+        let mut interval = tokio::time::interval(Duration::from_secs(poll_secs));
+        loop {
+            interval.tick().await;
+            // drain the queue before waiting for the next tick
+            loop {
+                match self.mint_next(batch_size).await {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(e) => {
+                        error!("Minter failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs `MINT_WORKERS` workers, each job is taken by a single one and each
+    /// transaction of up to `MINT_BATCH_SIZE` mints pays with its own coin of the gas pool.
+    pub async fn run(self) {
+        let poll_secs = env_or("MINT_POLL_SECS", DEFAULT_MINT_POLL_SECS);
+        let workers = env_or("MINT_WORKERS", DEFAULT_MINT_WORKERS);
+        let batch_size = env_or("MINT_BATCH_SIZE", DEFAULT_MINT_BATCH_SIZE);
+        let minter = Arc::new(self);
+        let workers = (0..workers.max(1))
+            .map(|_| tokio::spawn(minter.clone().work(poll_secs, batch_size.max(1))))
+            .collect::<Vec<_>>();
+        futures::future::join_all(workers).await;
+    }
+}
@@ -1,85 +1,438 @@
+pub mod anchor;
+pub mod attestation;
+pub mod gas;
 mod helpers;
+pub mod journal;
+pub mod minter;
+pub mod outcome;
+pub mod stream_feed;
+use crate::chat_log::ChatBatch;
+use crate::sui::gas::{GasConfig, GasPool};
 use crate::sui::helpers::setup_for_write;
+use crate::sui::journal::{JournalStatus, JournalSubject};
+use crate::sui::outcome::{ExecutionOutcome, called_packages, simulated_response};
+use crate::sui::stream_feed::FeedState;
 use anyhow::anyhow;
-use serde_json::json;
+use deadpool_postgres::Pool;
 use shared_crypto::intent::Intent;
-use std::error::Error;
+use std::collections::HashMap;
+use std::env;
 use sui_config::{SUI_KEYSTORE_FILENAME, sui_config_dir};
 use sui_keys::keystore::{AccountKeystore, FileBasedKeystore};
-use sui_sdk::json::SuiJsonValue;
-use sui_sdk::rpc_types::SuiTransactionBlockResponseOptions;
-use sui_sdk::types::Identifier;
-use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::rpc_types::{SuiObjectDataOptions, SuiTransactionBlockResponse};
+use sui_sdk::types::base_types::{ObjectID, ObjectRef, SequenceNumber, SuiAddress};
+use sui_sdk::types::digests::TransactionDigest;
+use sui_sdk::types::object::Owner;
 use sui_sdk::types::programmable_transaction_builder::ProgrammableTransactionBuilder;
-use sui_sdk::types::quorum_driver_types::ExecuteTransactionRequestType;
-use sui_sdk::types::transaction::{Argument, CallArg, Command, Transaction, TransactionData};
-use tracing::info;
-
-// hold image data through WALRUS
-pub async fn start_client() -> Result<(), Box<dyn Error>> {
-    let (sui, sender, _) = setup_for_write().await?;
-
-    let coins = sui
-        .coin_read_api()
-        .get_coins(sender, None, None, None)
-        .await?;
-    let coin = coins.data.into_iter().next().unwrap();
-
-    let mut ptb = ProgrammableTransactionBuilder::new();
-
-    let nft_name = "My NFT Name";
-    let input_argument = CallArg::Pure(bcs::to_bytes(&nft_name).unwrap());
-
-    // Add this input to the builder
-    ptb.input(input_argument)?;
-
-    let nft_desc = "description";
-    let input_argument = CallArg::Pure(bcs::to_bytes(&nft_desc).unwrap());
-
-    // Add this input to the builder
-    ptb.input(input_argument)?;
-
-    let nft_url = "url";
-    let input_argument = CallArg::Pure(bcs::to_bytes(&nft_url).unwrap());
-
-    // Add this input to the builder
-    ptb.input(input_argument)?;
-
-    let pkg_id = "0x87e1d6f71d7caa286ebab6dcb217d9426777112c2426fe8ef1ca3abacd78b179";
-    let package = ObjectID::from_hex_literal(pkg_id).map_err(|e| anyhow!(e))?;
-    let module = Identifier::new("nft").map_err(|e| anyhow!(e))?;
-    let function = Identifier::new("mint_to_sender").map_err(|e| anyhow!(e))?;
-    ptb.command(Command::move_call(
-        package,
-        module,
-        function,
-        vec![],
-        vec![Argument::Input(0), Argument::Input(1), Argument::Input(2)],
-    ));
-    let builder = ptb.finish();
-
-    let gas_budget = 10_000_000;
-    let gas_price = sui.read_api().get_reference_gas_price().await?;
-    // create the transaction data that will be sent to the network
-    let tx_data = TransactionData::new_programmable(
-        sender,
-        vec![coin.object_ref()],
-        builder,
-        gas_budget,
-        gas_price,
-    );
-    let keystore = FileBasedKeystore::new(&sui_config_dir()?.join(SUI_KEYSTORE_FILENAME))?;
-    let signature = keystore.sign_secure(&sender, &tx_data, Intent::sui_transaction())?;
-    info!("signature {:?}", signature);
-    info!("Executing the transaction...");
-    let transaction_response = sui
-        .quorum_driver_api()
-        .execute_transaction_block(
-            Transaction::from_data(tx_data, vec![signature]),
-            SuiTransactionBlockResponseOptions::full_content(),
-            Some(ExecuteTransactionRequestType::WaitForLocalExecution),
-        )
-        .await?;
-    info!("{:?}", transaction_response);
-    Ok(())
+use sui_sdk::types::transaction::{
+    ObjectArg, ProgrammableTransaction, Transaction, TransactionData,
+};
+use sui_sdk::types::{Identifier, SUI_CLOCK_OBJECT_ID, SUI_CLOCK_OBJECT_SHARED_VERSION};
+use sui_sdk::{SuiClient, SuiClientBuilder};
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// Client for reads only, e.g. to check proofs, on the network the oracle publishes to.
+pub async fn read_client() -> Result<SuiClient, anyhow::Error> {
+    Ok(SuiClientBuilder::default().build_testnet().await?)
+}
+
+/// Metadata of a minted `EmoNFT`.
+#[derive(Debug, Clone)]
+pub struct NftMetadata {
+    pub name: String,
+    pub description: String,
+    pub url: String,
+}
+
+/// One `EmoNFT` of a batch mint.
+#[derive(Debug, Clone)]
+pub struct NftMint {
+    /// Claim the NFT is minted for, journaled with the transaction.
+    pub claim_id: Uuid,
+    pub package: ObjectID,
+    /// Shared `nft::Minters` of `package` that lists the oracle.
+    pub minters: ObjectID,
+    pub recipient: SuiAddress,
+    pub metadata: NftMetadata,
+}
+
+/// The oracle's wallet: signs and executes transactions with the keystore key of `address`.
+pub struct Oracle {
+    client: SuiClient,
+    address: SuiAddress,
+    keystore: FileBasedKeystore,
+    /// Package used when a channel does not configure its own, `NFT_PACKAGE` env.
+    default_package: ObjectID,
+    /// Shared `nft::Minters` of the default package, `NFT_MINTERS` env.
+    default_minters: ObjectID,
+    gas: GasConfig,
+    gas_pool: GasPool,
+    /// Transactions are dry-run instead of executed, `--dry-run` flag. Their outcomes
+    /// are only recorded in `transaction_outcomes`, claims and batches stay as they are.
+    dry_run: bool,
+    /// Where every transaction is journaled before it is submitted and its outcome
+    /// is recorded.
+    journal: Option<Pool>,
+}
+
+impl Oracle {
+    // hold image data through WALRUS
+    pub async fn from_env() -> Result<Self, anyhow::Error> {
+        let (client, address, _) = setup_for_write().await?;
+        let keystore = FileBasedKeystore::new(&sui_config_dir()?.join(SUI_KEYSTORE_FILENAME))?;
+        // the package and its allowlist are published together, there is no usable default
+        let package = env::var("NFT_PACKAGE").map_err(|_| anyhow!("NFT_PACKAGE is not set"))?;
+        let default_package = ObjectID::from_hex_literal(&package).map_err(|e| anyhow!(e))?;
+        let minters = env::var("NFT_MINTERS").map_err(|_| anyhow!("NFT_MINTERS is not set"))?;
+        let default_minters = ObjectID::from_hex_literal(&minters).map_err(|e| anyhow!(e))?;
+        Ok(Self {
+            client,
+            address,
+            keystore,
+            default_package,
+            default_minters,
+            gas: GasConfig::from_env(),
+            gas_pool: GasPool::default(),
+            dry_run: false,
+            journal: None,
+        })
+    }
+
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn journal(&mut self, pool: Pool) -> &mut Self {
+        self.journal = Some(pool);
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn address(&self) -> SuiAddress {
+        self.address
+    }
+
+    pub fn default_package(&self) -> ObjectID {
+        self.default_package
+    }
+
+    pub fn default_minters(&self) -> ObjectID {
+        self.default_minters
+    }
+
+    /// Total SUI balance of the oracle, in MIST.
+    pub async fn balance(&self) -> Result<u128, anyhow::Error> {
+        let balance = self
+            .client
+            .coin_read_api()
+            .get_balance(self.address, None)
+            .await?;
+        Ok(balance.total_balance)
+    }
+
+    /// Version a shared object was shared at, needed to pass it to a transaction.
+    async fn initial_shared_version(
+        &self,
+        object: ObjectID,
+    ) -> Result<SequenceNumber, anyhow::Error> {
+        let owner = self
+            .client
+            .read_api()
+            .get_object_with_options(object, SuiObjectDataOptions::new().with_owner())
+            .await?
+            .data
+            .and_then(|data| data.owner)
+            .ok_or(anyhow!("Object {} not found", object))?;
+        match owner {
+            Owner::Shared {
+                initial_shared_version,
+            } => Ok(initial_shared_version),
+            _ => Err(anyhow!("Object {} is not shared", object)),
+        }
+    }
+
+    /// Mints an `EmoNFT` for each of `mints` in a single transaction. Returns the
+    /// response and the NFT of each mint, in the order of `mints`.
+    pub async fn mint_nfts(
+        &self,
+        mints: &[NftMint],
+    ) -> Result<(ExecutionOutcome, Vec<Option<ObjectID>>), anyhow::Error> {
+        let mut ptb = ProgrammableTransactionBuilder::new();
+        let mut shared_versions = HashMap::new();
+        for mint in mints {
+            let initial_shared_version = match shared_versions.get(&mint.minters) {
+                Some(version) => *version,
+                None => {
+                    let version = self.initial_shared_version(mint.minters).await?;
+                    shared_versions.insert(mint.minters, version);
+                    version
+                }
+            };
+            // read only, mints don't contend for the allowlist
+            let minters = ptb.obj(ObjectArg::SharedObject {
+                id: mint.minters,
+                initial_shared_version,
+                mutable: false,
+            })?;
+            let name = ptb.pure(mint.metadata.name.as_bytes())?;
+            let description = ptb.pure(mint.metadata.description.as_bytes())?;
+            let url = ptb.pure(mint.metadata.url.as_bytes())?;
+            let module = Identifier::new("nft").map_err(|e| anyhow!(e))?;
+            let function = Identifier::new("mint").map_err(|e| anyhow!(e))?;
+            let nft = ptb.programmable_move_call(
+                mint.package,
+                module,
+                function,
+                vec![],
+                vec![minters, name, description, url],
+            );
+            ptb.transfer_arg(mint.recipient, nft);
+        }
+        info!("Executing {} mint(s)...", mints.len());
+        let recipients: Vec<SuiAddress> = mints.iter().map(|mint| mint.recipient).collect();
+        let subject = JournalSubject::Mint {
+            claim_ids: mints.iter().map(|mint| mint.claim_id).collect(),
+            recipients: recipients.clone(),
+        };
+        let outcome = self.execute(ptb.finish(), &subject).await?;
+        let nfts = outcome.created_for(&recipients);
+        Ok((outcome, nfts))
+    }
+
+    /// Publishes the Merkle root of `batch` as a frozen `chat_log::ChatBatch`,
+    /// authorized by the oracle's `AnchorCap`. Returns the digest and the batch object.
+    pub async fn publish_chat_batch(
+        &self,
+        package: ObjectID,
+        cap: ObjectID,
+        batch: &ChatBatch,
+    ) -> Result<(TransactionDigest, ObjectID), anyhow::Error> {
+        let cap_ref = self
+            .client
+            .read_api()
+            .get_object_with_options(cap, SuiObjectDataOptions::new())
+            .await?
+            .object_ref_if_exists()
+            .ok_or(anyhow!("Anchor cap {} not found", cap))?;
+
+        let pt = Self::publish_batch_call(package, cap_ref, batch)?;
+        let outcome = self
+            .execute(pt, &JournalSubject::ChatBatch { batch_id: batch.id })
+            .await?;
+        let batch = outcome
+            .created
+            .first()
+            .ok_or(anyhow!("Transaction {} created no batch", outcome.digest))?
+            .object_id;
+        Ok((outcome.digest, batch))
+    }
+
+    /// `chat_log::publish_batch` of `batch`, whose byte arguments are `vector<u8>`.
+    fn publish_batch_call(
+        package: ObjectID,
+        cap_ref: ObjectRef,
+        batch: &ChatBatch,
+    ) -> Result<ProgrammableTransaction, anyhow::Error> {
+        let mut ptb = ProgrammableTransactionBuilder::new();
+        let arguments = vec![
+            ptb.obj(ObjectArg::ImmOrOwnedObject(cap_ref))?,
+            // a slice, `&[u8; 16]` would be encoded without the length of a vector
+            ptb.pure(&batch.id.as_bytes()[..])?,
+            ptb.pure(batch.channel_id as u64)?,
+            ptb.pure(&batch.root[..])?,
+            ptb.pure(batch.leaf_count as u64)?,
+            ptb.pure(batch.first_message_ms as u64)?,
+            ptb.pure(batch.last_message_ms as u64)?,
+        ];
+        let module = Identifier::new("chat_log").map_err(|e| anyhow!(e))?;
+        let function = Identifier::new("publish_batch").map_err(|e| anyhow!(e))?;
+        ptb.programmable_move_call(package, module, function, vec![], arguments);
+        Ok(ptb.finish())
+    }
+
+    /// Writes the stream stats of `channel_login` to its shared `stream_feed::StreamFeed`,
+    /// authorized by the oracle's `FeedAdminCap`.
+    pub async fn update_stream_feed(
+        &self,
+        package: ObjectID,
+        cap: ObjectID,
+        feed: ObjectID,
+        channel_login: &str,
+        state: &FeedState,
+    ) -> Result<TransactionDigest, anyhow::Error> {
+        let cap_ref = self
+            .client
+            .read_api()
+            .get_object_with_options(cap, SuiObjectDataOptions::new())
+            .await?
+            .object_ref_if_exists()
+            .ok_or(anyhow!("Feed admin cap {} not found", cap))?;
+        let initial_shared_version = self.initial_shared_version(feed).await?;
+
+        let mut ptb = ProgrammableTransactionBuilder::new();
+        let arguments = vec![
+            ptb.obj(ObjectArg::ImmOrOwnedObject(cap_ref))?,
+            ptb.obj(ObjectArg::SharedObject {
+                id: feed,
+                initial_shared_version,
+                mutable: true,
+            })?,
+            ptb.pure(state.live)?,
+            ptb.pure(state.viewer_count as u64)?,
+            ptb.pure(state.game_id.as_bytes())?,
+            ptb.pure(state.game_name.as_bytes())?,
+            ptb.obj(ObjectArg::SharedObject {
+                id: SUI_CLOCK_OBJECT_ID,
+                initial_shared_version: SUI_CLOCK_OBJECT_SHARED_VERSION,
+                mutable: false,
+            })?,
+        ];
+        let module = Identifier::new("stream_feed").map_err(|e| anyhow!(e))?;
+        let function = Identifier::new("update").map_err(|e| anyhow!(e))?;
+        ptb.programmable_move_call(package, module, function, vec![], arguments);
+        let subject = JournalSubject::StreamFeed {
+            channel_login: channel_login.to_string(),
+            feed,
+            state: state.clone(),
+        };
+        let outcome = self.execute(ptb.finish(), &subject).await?;
+        Ok(outcome.digest)
+    }
+
+    /// Signs `pt` with the oracle's key, pays with a coin checked out of the gas pool
+    /// and waits for execution. Concurrent calls use different coins. Transactions
+    /// that failed on chain are errors, their outcome is recorded all the same.
+    async fn execute(
+        &self,
+        pt: ProgrammableTransaction,
+        subject: &JournalSubject,
+    ) -> Result<ExecutionOutcome, anyhow::Error> {
+        let packages = called_packages(&pt);
+        let gas = self.checkout_gas(self.gas.budget_cap).await?;
+        let response = self.execute_with_gas(pt, gas.object_ref, subject).await;
+        self.return_gas(&gas, response.as_ref());
+        self.outcome_of(&response?, subject, &packages).await
+    }
+
+    /// Parses the effects of `response`, records them in `transaction_outcomes` and
+    /// settles the transaction: a success is recorded for `subject`, a failure is
+    /// left to the caller. When settling fails the journal is resolved later,
+    /// see [`journal::JournalResolver`]. `packages` are the packages the transaction
+    /// called, see [`outcome::called_packages`].
+    async fn outcome_of(
+        &self,
+        response: &SuiTransactionBlockResponse,
+        subject: &JournalSubject,
+        packages: &[ObjectID],
+    ) -> Result<ExecutionOutcome, anyhow::Error> {
+        let outcome = ExecutionOutcome::from_response(response, self.dry_run, packages)?;
+        match &outcome.error {
+            None => info!(
+                "Transaction {} succeeded, {} MIST of gas",
+                outcome.digest,
+                outcome.gas_used()
+            ),
+            Some(error) => error!("Transaction {} failed: {}", outcome.digest, error),
+        }
+        self.record_outcome(&outcome).await;
+        if outcome.is_success() {
+            if let Err(e) = self.settle(subject, &outcome).await {
+                error!(
+                    "Failed to settle transaction {}, it is settled by the journal: {:?}",
+                    outcome.digest, e
+                );
+            }
+        } else {
+            self.journal_mark(
+                &outcome.digest,
+                JournalStatus::Settled,
+                outcome.error.as_deref(),
+            )
+            .await;
+        }
+        Ok(outcome.into_result()?)
+    }
+
+    /// Saves `outcome` to `transaction_outcomes`. The transaction happened, or was
+    /// simulated, all the same, so a failed insert is only logged.
+    async fn record_outcome(&self, outcome: &ExecutionOutcome) {
+        let Some(pool) = &self.journal else {
+            return;
+        };
+        let saved = async {
+            let client = pool.get().await?;
+            outcome.save(&client).await
+        };
+        if let Err(e) = saved.await {
+            error!("Failed to record outcome of {}: {:?}", outcome.digest, e);
+        }
+    }
+
+    /// Signs `pt` with the oracle's key, pays with `gas` and waits for execution.
+    /// The budget is estimated by a dry run, see [`Oracle::estimate_budget`].
+    /// In dry-run mode the signed transaction is not executed, the response
+    /// carries the simulated effects instead. Otherwise it is journaled with
+    /// `subject` before it is submitted, see [`Oracle::recover`].
+    async fn execute_with_gas(
+        &self,
+        pt: ProgrammableTransaction,
+        gas: ObjectRef,
+        subject: &JournalSubject,
+    ) -> Result<SuiTransactionBlockResponse, anyhow::Error> {
+        let gas_price = self.client.read_api().get_reference_gas_price().await?;
+        let (budget, simulated) = self.estimate_budget(&pt, gas, gas_price).await?;
+        // create the transaction data that will be sent to the network
+        let tx_data =
+            TransactionData::new_programmable(self.address, vec![gas], pt, budget, gas_price);
+        let signature =
+            self.keystore
+                .sign_secure(&self.address, &tx_data, Intent::sui_transaction())?;
+        if self.dry_run {
+            // the effects are those of the estimate, which ran with the cap as budget
+            return Ok(simulated_response(simulated));
+        }
+        let transaction = Transaction::from_data(tx_data, vec![signature]);
+        self.journal_pending(&transaction, subject).await?;
+        let response = self.submit(&transaction).await?;
+        self.journal_mark(&response.digest, JournalStatus::Confirmed, None)
+            .await;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sui_sdk::types::digests::ObjectDigest;
+    use sui_sdk::types::transaction::CallArg;
+
+    #[test]
+    fn batch_id_is_encoded_as_a_vector() {
+        let batch = ChatBatch {
+            id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            channel_id: 141981764,
+            channel_login: "twitchdev".to_string(),
+            root: vec![7; 32],
+            leaf_count: 3,
+            first_message_ms: 1_700_000_000_000,
+            last_message_ms: 1_700_000_060_000,
+        };
+        let cap_ref = (ObjectID::ZERO, SequenceNumber::new(), ObjectDigest::MIN);
+        let pt = Oracle::publish_batch_call(ObjectID::ZERO, cap_ref, &batch).unwrap();
+        let CallArg::Pure(batch_id) = &pt.inputs[1] else {
+            panic!("batch_id is not a pure argument");
+        };
+        // ULEB128 length 16, then the UUID
+        assert_eq!(batch_id[0], 0x10);
+        assert_eq!(&batch_id[1..], batch.id.as_bytes());
+        let CallArg::Pure(root) = &pt.inputs[3] else {
+            panic!("root is not a pure argument");
+        };
+        assert_eq!(root[0], 32);
+    }
 }
@@ -0,0 +1,482 @@
+use crate::GlobalError;
+use crate::pg::PgClient;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use sui_sdk::rpc_types::{
+    DryRunTransactionBlockResponse, SuiExecutionStatus, SuiTransactionBlockEffectsAPI,
+    SuiTransactionBlockResponse,
+};
+use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::types::digests::TransactionDigest;
+use sui_sdk::types::effects::{TransactionEffects, TransactionEffectsAPI};
+use sui_sdk::types::execution_status::{ExecutionFailureStatus, ExecutionStatus};
+use sui_sdk::types::object::Owner;
+use sui_sdk::types::transaction::{Command, ProgrammableTransaction};
+use tracing::warn;
+
+/// An `abort` in a Move function the transaction called.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MoveAbort {
+    /// Package of the aborting module.
+    pub package: ObjectID,
+    pub module: String,
+    pub function: Option<String>,
+    pub code: u64,
+    /// Meaning of `code`, known for the oracle's packages only.
+    pub reason: Option<&'static str>,
+}
+
+impl MoveAbort {
+    /// The abort of a failed execution, `None` for any other failure. `packages` are
+    /// the packages the transaction called, see [`MoveAbort::in_packages`].
+    pub fn from_failure(failure: &ExecutionFailureStatus, packages: &[ObjectID]) -> Option<Self> {
+        match failure {
+            ExecutionFailureStatus::MoveAbort(location, code) => Some(
+                Self {
+                    package: ObjectID::from(*location.module.address()),
+                    module: location.module.name().to_string(),
+                    function: location.function_name.clone(),
+                    code: *code,
+                    reason: None,
+                }
+                .in_packages(packages),
+            ),
+            _ => None,
+        }
+    }
+
+    /// The abort in the message of a JSON status, which is the `Debug` output of the
+    /// failure, e.g. of a dry run that has no BCS effects.
+    pub fn from_message(message: &str, packages: &[ObjectID]) -> Option<Self> {
+        let rest = message.strip_prefix("MoveAbort(MoveLocation { module: ModuleId { address: ")?;
+        let (address, rest) = rest.split_once(", name: Identifier(\"")?;
+        let (module, rest) = rest.split_once("\") }")?;
+        let (_, rest) = rest.split_once("function_name: ")?;
+        let (function, rest) = rest.split_once(" }, ")?;
+        let (code, _) = rest.split_once(')')?;
+        let address = format!("0x{}", address.trim_start_matches("0x"));
+        Some(
+            Self {
+                package: ObjectID::from_hex_literal(&address).ok()?,
+                module: module.to_string(),
+                function: function
+                    .strip_prefix("Some(\"")
+                    .and_then(|function| function.strip_suffix("\")"))
+                    .map(str::to_string),
+                code: code.parse().ok()?,
+                reason: None,
+            }
+            .in_packages(packages),
+        )
+    }
+
+    /// Looks up the meaning of the code when the abort is in one of `packages`. The
+    /// oracle only calls its configured packages, a module of the same name in any
+    /// other package, e.g. a dependency, has codes of its own.
+    fn in_packages(mut self, packages: &[ObjectID]) -> Self {
+        if !packages.contains(&self.package) {
+            return self;
+        }
+        self.reason = match (self.module.as_str(), self.code) {
+            ("chat_log", 0) => Some("the batch root is not 32 bytes"),
+            ("chat_log", 1) => Some("the batch has no messages"),
+            ("claim", 0) => Some("the attestation signature is invalid"),
+            ("claim", 1) => Some("the attestation nonce was already used"),
+            ("claim", 2) => Some("the attestation expired"),
+            ("claim", 3) => Some("the claim registry has no oracle key"),
+            ("nft", 0) => Some("the oracle is not in the package's Minters"),
+            _ => None,
+        };
+        self
+    }
+}
+
+/// Packages of the Move calls of `pt`.
+pub fn called_packages(pt: &ProgrammableTransaction) -> Vec<ObjectID> {
+    pt.commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::MoveCall(call) => Some(call.package),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Display for MoveAbort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}::{} aborted with code {}",
+            self.module,
+            self.function.as_deref().unwrap_or("?"),
+            self.code
+        )?;
+        match self.reason {
+            Some(reason) => write!(f, ", {}", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecutionError {
+    /// The transaction aborted in Move.
+    MoveAbort {
+        digest: TransactionDigest,
+        abort: MoveAbort,
+    },
+    /// Any other failure, e.g. running out of gas.
+    Failed {
+        digest: TransactionDigest,
+        error: String,
+    },
+    MissingEffects(TransactionDigest),
+    /// The dry run needs more gas than a transaction may spend.
+    OverBudget {
+        estimate: u64,
+        cap: u64,
+    },
+    /// Submitted but its effects never came back, it may still execute.
+    Unconfirmed {
+        digest: TransactionDigest,
+        error: String,
+    },
+}
+
+impl ExecutionError {
+    /// Whether the transaction could not execute as built, on chain or in the dry run,
+    /// e.g. an abort or too much gas. Any other error says nothing about the transaction.
+    pub fn is_execution_failure(&self) -> bool {
+        matches!(
+            self,
+            ExecutionError::MoveAbort { .. }
+                | ExecutionError::Failed { .. }
+                | ExecutionError::OverBudget { .. }
+        )
+    }
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionError::MoveAbort { digest, abort } => {
+                write!(f, "Transaction {} failed: {}", digest, abort)
+            }
+            ExecutionError::Failed { digest, error } => {
+                write!(f, "Transaction {} failed: {}", digest, error)
+            }
+            ExecutionError::MissingEffects(digest) => {
+                write!(f, "Transaction {} has no effects", digest)
+            }
+            ExecutionError::OverBudget { estimate, cap } => write!(
+                f,
+                "Estimated gas budget {} exceeds the cap of {} MIST",
+                estimate, cap
+            ),
+            ExecutionError::Unconfirmed { digest, error } => {
+                write!(f, "Transaction {} is not confirmed: {}", digest, error)
+            }
+        }
+    }
+}
+
+impl Error for ExecutionError {}
+
+/// Readable error and abort of an execution status, both `None` on success.
+/// `packages` are the packages the transaction called.
+pub fn classify(
+    status: &ExecutionStatus,
+    packages: &[ObjectID],
+) -> (Option<String>, Option<MoveAbort>) {
+    match status {
+        ExecutionStatus::Success => (None, None),
+        ExecutionStatus::Failure { error, command } => {
+            let abort = MoveAbort::from_failure(error, packages);
+            let message = match (&abort, command) {
+                (Some(abort), _) => abort.to_string(),
+                (None, Some(command)) => format!("{:?} in command {}", error, command),
+                (None, None) => format!("{:?}", error),
+            };
+            (Some(message), abort)
+        }
+    }
+}
+
+/// Like [`classify`], for the JSON status of effects that are not available as BCS.
+pub fn classify_json(
+    status: &SuiExecutionStatus,
+    packages: &[ObjectID],
+) -> (Option<String>, Option<MoveAbort>) {
+    match status {
+        SuiExecutionStatus::Success => (None, None),
+        SuiExecutionStatus::Failure { error } => match MoveAbort::from_message(error, packages) {
+            Some(abort) => (Some(abort.to_string()), Some(abort)),
+            None => (Some(error.clone()), None),
+        },
+    }
+}
+
+/// The response of a dry run, as if the transaction had been executed.
+pub fn simulated_response(dry_run: DryRunTransactionBlockResponse) -> SuiTransactionBlockResponse {
+    let digest = *dry_run.effects.transaction_digest();
+    let mut response = SuiTransactionBlockResponse::new(digest);
+    response.effects = Some(dry_run.effects);
+    response.events = Some(dry_run.events);
+    response.object_changes = Some(dry_run.object_changes);
+    response.balance_changes = Some(dry_run.balance_changes);
+    response
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedObject {
+    pub object_id: ObjectID,
+    /// Address that owns the object, `None` for shared, immutable and wrapped objects.
+    pub owner: Option<SuiAddress>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutcomeEvent {
+    /// Move type of the event, e.g. `<package>::chat_log::BatchPublished`.
+    pub event_type: String,
+    pub json: serde_json::Value,
+}
+
+/// What an executed, or in dry-run mode simulated, transaction did.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionOutcome {
+    pub digest: TransactionDigest,
+    /// Readable error of a failed transaction, `None` when it succeeded.
+    pub error: Option<String>,
+    pub abort: Option<MoveAbort>,
+    pub created: Vec<CreatedObject>,
+    pub mutated: Vec<ObjectID>,
+    pub computation_cost: u64,
+    pub storage_cost: u64,
+    pub storage_rebate: u64,
+    pub events: Vec<OutcomeEvent>,
+    pub simulated: bool,
+}
+
+impl ExecutionOutcome {
+    /// `packages` are the packages the transaction called, see [`called_packages`].
+    pub fn from_response(
+        response: &SuiTransactionBlockResponse,
+        simulated: bool,
+        packages: &[ObjectID],
+    ) -> Result<Self, ExecutionError> {
+        let effects = response
+            .effects
+            .as_ref()
+            .ok_or(ExecutionError::MissingEffects(response.digest))?;
+        // only the BCS effects keep the failure structured, the JSON ones have its message
+        let raw = if response.raw_effects.is_empty() {
+            None
+        } else {
+            match bcs::from_bytes::<TransactionEffects>(&response.raw_effects) {
+                Ok(raw) => Some(raw),
+                Err(e) => {
+                    warn!("Failed to decode effects of {}: {}", response.digest, e);
+                    None
+                }
+            }
+        };
+        let (error, abort) = match raw {
+            Some(raw) => classify(raw.status(), packages),
+            None => classify_json(effects.status(), packages),
+        };
+        let gas = effects.gas_cost_summary();
+        Ok(Self {
+            digest: response.digest,
+            error,
+            abort,
+            created: effects
+                .created()
+                .iter()
+                .map(|object| CreatedObject {
+                    object_id: object.object_id(),
+                    owner: match object.owner {
+                        Owner::AddressOwner(address) => Some(address),
+                        _ => None,
+                    },
+                })
+                .collect(),
+            mutated: effects
+                .mutated()
+                .iter()
+                .map(|object| object.object_id())
+                .collect(),
+            computation_cost: gas.computation_cost,
+            storage_cost: gas.storage_cost,
+            storage_rebate: gas.storage_rebate,
+            events: response
+                .events
+                .iter()
+                .flat_map(|events| &events.data)
+                .map(|event| OutcomeEvent {
+                    event_type: event.type_.to_string(),
+                    json: event.parsed_json.clone(),
+                })
+                .collect(),
+            simulated,
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The outcome if the transaction succeeded, otherwise why it failed.
+    pub fn into_result(self) -> Result<Self, ExecutionError> {
+        match (&self.error, &self.abort) {
+            (None, _) => Ok(self),
+            (Some(_), Some(abort)) => Err(ExecutionError::MoveAbort {
+                digest: self.digest,
+                abort: abort.clone(),
+            }),
+            (Some(error), None) => Err(ExecutionError::Failed {
+                digest: self.digest,
+                error: error.clone(),
+            }),
+        }
+    }
+
+    /// The object created by each of the first `object::new` calls of the transaction,
+    /// if it went to the matching recipient. A mint creates exactly one object, so
+    /// this maps the mints of a batch to their NFTs.
+    pub fn created_for(&self, recipients: &[SuiAddress]) -> Vec<Option<ObjectID>> {
+        recipients
+            .iter()
+            .enumerate()
+            .map(|(i, recipient)| {
+                let id = ObjectID::derive_id(self.digest, i as u64);
+                self.created
+                    .iter()
+                    .any(|object| object.object_id == id && object.owner == Some(*recipient))
+                    .then_some(id)
+            })
+            .collect()
+    }
+
+    /// Net gas paid, storage rebates can make it negative.
+    pub fn gas_used(&self) -> i64 {
+        self.computation_cost as i64 + self.storage_cost as i64 - self.storage_rebate as i64
+    }
+
+    pub async fn save(&self, client: &PgClient) -> Result<(), GlobalError> {
+        let query = "INSERT INTO transaction_outcomes (digest, success, error, abort_package, abort_module, abort_code, \
+             computation_cost, storage_cost, storage_rebate, created, mutated, events, simulated) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) \
+             ON CONFLICT (digest) DO NOTHING";
+        client
+            .execute(
+                query,
+                &[
+                    &self.digest.to_string(),
+                    &self.is_success(),
+                    &self.error,
+                    &self.abort.as_ref().map(|abort| abort.package.to_string()),
+                    &self.abort.as_ref().map(|abort| abort.module.as_str()),
+                    &self.abort.as_ref().map(|abort| abort.code as i64),
+                    &(self.computation_cost as i64),
+                    &(self.storage_cost as i64),
+                    &(self.storage_rebate as i64),
+                    &serde_json::to_value(&self.created)?,
+                    &serde_json::to_value(&self.mutated)?,
+                    &serde_json::to_value(&self.events)?,
+                    &self.simulated,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sui_sdk::types::execution_status::MoveLocation;
+    use sui_sdk::types::parse_sui_struct_tag;
+
+    const PACKAGE: &str = "0xc1a1";
+
+    fn package() -> ObjectID {
+        ObjectID::from_hex_literal(PACKAGE).unwrap()
+    }
+
+    fn abort_in(package: &str, module: &str, function: &str, code: u64) -> ExecutionStatus {
+        let tag = parse_sui_struct_tag(&format!("{}::{}::Any", package, module)).unwrap();
+        ExecutionStatus::Failure {
+            error: ExecutionFailureStatus::MoveAbort(
+                MoveLocation {
+                    module: tag.module_id(),
+                    function: 2,
+                    instruction: 20,
+                    function_name: Some(function.to_string()),
+                },
+                code,
+            ),
+            command: Some(0),
+        }
+    }
+
+    #[test]
+    fn success_has_no_error() {
+        assert_eq!(classify(&ExecutionStatus::Success, &[]), (None, None));
+    }
+
+    #[test]
+    fn abort_keeps_package_module_and_code() {
+        let (error, abort) = classify(&abort_in(PACKAGE, "claim", "claim", 1), &[package()]);
+        let abort = abort.unwrap();
+        assert_eq!(
+            abort,
+            MoveAbort {
+                package: package(),
+                module: "claim".to_string(),
+                function: Some("claim".to_string()),
+                code: 1,
+                reason: Some("the attestation nonce was already used"),
+            }
+        );
+        assert_eq!(
+            error.unwrap(),
+            "claim::claim aborted with code 1, the attestation nonce was already used"
+        );
+    }
+
+    #[test]
+    fn abort_in_another_package_has_no_reason() {
+        let (error, abort) = classify(&abort_in("0xbad", "claim", "claim", 1), &[package()]);
+        assert_eq!(abort.unwrap().reason, None);
+        assert_eq!(error.unwrap(), "claim::claim aborted with code 1");
+    }
+
+    #[test]
+    fn json_abort_is_classified_like_the_bcs_one() {
+        let status = abort_in(PACKAGE, "nft", "mint", 0);
+        let ExecutionStatus::Failure { error, command } = &status else {
+            unreachable!();
+        };
+        // how the JSON effects format a failure
+        let json = SuiExecutionStatus::Failure {
+            error: format!("{:?} in command {}", error, command.unwrap()),
+        };
+        assert_eq!(
+            classify_json(&json, &[package()]),
+            classify(&status, &[package()])
+        );
+    }
+
+    #[test]
+    fn out_of_gas_is_no_abort() {
+        let status = ExecutionStatus::Failure {
+            error: ExecutionFailureStatus::InsufficientGas,
+            command: None,
+        };
+        assert_eq!(
+            classify(&status, &[]),
+            (Some("InsufficientGas".to_string()), None)
+        );
+    }
+}
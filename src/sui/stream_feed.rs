@@ -0,0 +1,211 @@
+use crate::GlobalError;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use crate::sui::Oracle;
+use crate::twitch::channel_config::ChannelsConfig;
+use crate::twitch::{StreamInfo, TwitchApi};
+use anyhow::anyhow;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use sui_sdk::types::base_types::ObjectID;
+use tracing::{error, info};
+
+const DEFAULT_FEED_INTERVAL_SECS: u64 = 300;
+const DEFAULT_FEED_VIEWER_THRESHOLD_PCT: u32 = 10;
+const DEFAULT_FEED_MAX_AGE_SECS: i64 = 60 * 60;
+
+/// Stream stats as written to a `stream_feed::StreamFeed`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedState {
+    pub live: bool,
+    pub viewer_count: u32,
+    pub game_id: String,
+    pub game_name: String,
+}
+
+impl FeedState {
+    /// An offline stream has no viewers and no game.
+    pub fn from_info(info: Option<&StreamInfo>) -> Self {
+        match info {
+            Some(info) => Self {
+                live: true,
+                viewer_count: info.viewer_count,
+                game_id: info.game_id.clone(),
+                game_name: info.game_name.clone(),
+            },
+            None => Self {
+                live: false,
+                viewer_count: 0,
+                game_id: String::new(),
+                game_name: String::new(),
+            },
+        }
+    }
+
+    /// Whether the stream went live or offline, switched game, or the viewer
+    /// count moved by at least `threshold_pct` percent since `last`.
+    pub fn changed_from(&self, last: &FeedState, threshold_pct: u32) -> bool {
+        if self.live != last.live || self.game_id != last.game_id {
+            return true;
+        }
+        let delta = self.viewer_count.abs_diff(last.viewer_count) as u64;
+        delta > 0 && delta * 100 >= threshold_pct as u64 * last.viewer_count.max(1) as u64
+    }
+
+    /// Last state published to `feed` and its age in seconds, dry runs are not
+    /// on chain and don't count.
+    async fn last_published(
+        client: &PgClient,
+        feed: &ObjectID,
+    ) -> Result<Option<(Self, i64)>, GlobalError> {
+        let row = client
+            .query_opt(
+                "SELECT live, viewer_count, game_id, game_name, \
+                 extract(epoch from current_timestamp - created_at)::bigint \
+                 FROM stream_feed_updates WHERE feed_id = $1 AND NOT simulated \
+                 ORDER BY created_at DESC LIMIT 1",
+                &[&feed.to_string()],
+            )
+            .await?;
+        Ok(row.map(|row| {
+            let viewer_count: i32 = row.get(1);
+            let state = Self {
+                live: row.get(0),
+                viewer_count: viewer_count as u32,
+                game_id: row.get(2),
+                game_name: row.get(3),
+            };
+            (state, row.get(4))
+        }))
+    }
+
+    pub(crate) async fn record(
+        &self,
+        transaction: &Transaction<'_>,
+        channel_login: &str,
+        feed: &ObjectID,
+        digest: &str,
+        simulated: bool,
+    ) -> Result<(), GlobalError> {
+        transaction
+            .execute(
+                "INSERT INTO stream_feed_updates (channel_login, feed_id, live, viewer_count, game_id, game_name, digest, simulated) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &channel_login,
+                    &feed.to_string(),
+                    &self.live,
+                    &(self.viewer_count as i32),
+                    &self.game_id,
+                    &self.game_name,
+                    &digest,
+                    &simulated,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Publishes the live status, viewer count and game of every channel with a
+/// `stream_feed` to its shared `StreamFeed` on Sui. Small changes are skipped to
+/// save gas, the feed is still refreshed once it is older than the max age.
+pub struct StreamFeedPublisher {
+    oracle: Arc<Oracle>,
+    pool: Pool,
+    twitch: Arc<TwitchApi>,
+    channels: Arc<ChannelsConfig>,
+    /// `stream_feed` package, `STREAM_FEED_PACKAGE` env.
+    package: ObjectID,
+    /// `FeedAdminCap` owned by the oracle, `STREAM_FEED_CAP` env.
+    cap: ObjectID,
+}
+
+impl StreamFeedPublisher {
+    /// Returns `None` when `STREAM_FEED_PACKAGE` or `STREAM_FEED_CAP` is not set.
+    pub fn from_env(
+        oracle: Arc<Oracle>,
+        pool: Pool,
+        twitch: Arc<TwitchApi>,
+        channels: Arc<ChannelsConfig>,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let (Ok(package), Ok(cap)) = (
+            std::env::var("STREAM_FEED_PACKAGE"),
+            std::env::var("STREAM_FEED_CAP"),
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            oracle,
+            pool,
+            twitch,
+            channels,
+            package: ObjectID::from_hex_literal(&package).map_err(|e| anyhow!(e))?,
+            cap: ObjectID::from_hex_literal(&cap).map_err(|e| anyhow!(e))?,
+        }))
+    }
+
+    async fn publish(
+        &self,
+        channel_login: &str,
+        feed: &str,
+        threshold_pct: u32,
+        max_age_secs: i64,
+    ) -> Result<(), GlobalError> {
+        let feed = ObjectID::from_hex_literal(feed).map_err(|e| anyhow!(e))?;
+        let info = self.twitch.get_stream_info(channel_login).await?;
+        let state = FeedState::from_info(info.as_ref());
+        let client = self.pool.get().await?;
+        if let Some((last, age_secs)) = FeedState::last_published(&client, &feed).await?
+            && age_secs < max_age_secs
+            && !state.changed_from(&last, threshold_pct)
+        {
+            return Ok(());
+        }
+        // the update is recorded when its transaction is settled
+        let digest = self
+            .oracle
+            .update_stream_feed(self.package, self.cap, feed, channel_login, &state)
+            .await?;
+        info!(
+            "Updated stream feed of {}: live {}, {} viewers: {}{}",
+            channel_login,
+            state.live,
+            state.viewer_count,
+            digest,
+            if self.oracle.is_dry_run() {
+                " (dry run)"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        let interval_secs = env_or("STREAM_FEED_INTERVAL_SECS", DEFAULT_FEED_INTERVAL_SECS);
+        let threshold_pct = env_or(
+            "STREAM_FEED_VIEWER_THRESHOLD_PCT",
+            DEFAULT_FEED_VIEWER_THRESHOLD_PCT,
+        );
+        let max_age_secs = env_or("STREAM_FEED_MAX_AGE_SECS", DEFAULT_FEED_MAX_AGE_SECS);
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            for login in self.channels.logins() {
+                let Some(feed) = self
+                    .channels
+                    .get(login)
+                    .and_then(|c| c.stream_feed.as_ref())
+                else {
+                    continue;
+                };
+                if let Err(e) = self.publish(login, feed, threshold_pct, max_age_secs).await {
+                    error!("Failed to update stream feed of {}: {:?}", login, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::claims::rewards::RewardRule;
+use crate::twitch::commands::Role;
+use crate::twitch::commands::cooldown::CooldownConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn default_prefix() -> String {
+    "!".to_string()
+}
+
+fn default_commands() -> Vec<String> {
+    [
+        "!STORE", "!NFT", "!PAUSE", "!RESUME", "!AIRDROP", "!REVOKE", "!STATUS",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Per-channel settings of the oracle.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelConfig {
+    /// Twitch login of the broadcaster, the channel that is joined.
+    pub login: String,
+    /// Marks a chat line as a command, `!` by default.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Commands that are handled in this channel, e.g. `!STORE`.
+    #[serde(default = "default_commands")]
+    pub commands: Vec<String>,
+    /// Overrides the role a command requires, e.g. `{"NFT": "subscriber"}`.
+    #[serde(default)]
+    pub command_roles: HashMap<String, Role>,
+    /// Cooldowns applied to every command of the channel.
+    #[serde(default)]
+    pub cooldowns: CooldownConfig,
+    /// Replaces `cooldowns` for a single command, e.g. `{"NFT": {"per_user_secs": 600}}`.
+    #[serde(default)]
+    pub command_cooldowns: HashMap<String, CooldownConfig>,
+    /// Package the channel's NFTs are minted from, the global one is used when empty.
+    #[serde(default)]
+    pub nft_package: Option<String>,
+    /// Shared `nft::Minters` of `nft_package` that lists the oracle,
+    /// required when the channel has its own package.
+    #[serde(default)]
+    pub nft_minters: Option<String>,
+    /// Name of the minted NFT, `<login> stream NFT` by default.
+    #[serde(default)]
+    pub nft_name: Option<String>,
+    #[serde(default)]
+    pub nft_description: Option<String>,
+    /// Image of the NFT.
+    #[serde(default)]
+    pub nft_url: Option<String>,
+    /// How many `!NFT` claims a single viewer can make in this channel.
+    #[serde(default)]
+    pub max_claims_per_user: Option<i64>,
+    /// Longer `!STORE` messages are rejected.
+    #[serde(default)]
+    pub max_message_length: Option<usize>,
+    /// NFTs minted for subs, raids and cheers.
+    #[serde(default)]
+    pub rewards: Vec<RewardRule>,
+    /// Archives every chat line with its IRC tags, not only commands.
+    #[serde(default)]
+    pub archive_chat: bool,
+    /// Shared `stream_feed::StreamFeed` the channel's live stats are published to.
+    #[serde(default)]
+    pub stream_feed: Option<String>,
+}
+
+impl ChannelConfig {
+    pub fn new(login: String) -> Self {
+        Self {
+            login: login.to_lowercase(),
+            prefix: default_prefix(),
+            commands: default_commands(),
+            command_roles: HashMap::new(),
+            cooldowns: CooldownConfig::default(),
+            command_cooldowns: HashMap::new(),
+            nft_package: None,
+            nft_minters: None,
+            nft_name: None,
+            nft_description: None,
+            nft_url: None,
+            max_claims_per_user: None,
+            max_message_length: None,
+            rewards: Vec::new(),
+            archive_chat: false,
+            stream_feed: None,
+        }
+    }
+
+    /// Role the channel requires for `command`, if it overrides the command's default.
+    pub fn required_role(&self, command: &str) -> Option<Role> {
+        let command = command.trim_start_matches('!');
+        self.command_roles
+            .iter()
+            .find(|(c, _)| c.trim_start_matches('!').eq_ignore_ascii_case(command))
+            .map(|(_, role)| *role)
+    }
+
+    pub fn cooldown(&self, command: &str) -> CooldownConfig {
+        let command = command.trim_start_matches('!');
+        self.command_cooldowns
+            .iter()
+            .find(|(c, _)| c.trim_start_matches('!').eq_ignore_ascii_case(command))
+            .map(|(_, cooldown)| *cooldown)
+            .unwrap_or(self.cooldowns)
+    }
+
+    /// Whether `command` (with or without the `!` prefix) is enabled.
+    pub fn is_enabled(&self, command: &str) -> bool {
+        let command = command.trim_start_matches('!');
+        self.commands
+            .iter()
+            .any(|c| c.trim_start_matches('!').eq_ignore_ascii_case(command))
+    }
+}
+
+/// The set of channels the oracle listens to, keyed by channel login.
+#[derive(Debug, Clone)]
+pub struct ChannelsConfig {
+    channels: HashMap<String, ChannelConfig>,
+}
+
+impl ChannelsConfig {
+    /// Reads the channels from the JSON file in `CHANNELS_CONFIG`,
+    /// falling back to the comma separated `STREAMERS` or the single `STREAMER`.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let channels: Vec<ChannelConfig> = match env::var("CHANNELS_CONFIG") {
+            Ok(path) => serde_json::from_str(&fs::read_to_string(path)?)?,
+            Err(_) => {
+                let streamers = env::var("STREAMERS").or_else(|_| env::var("STREAMER"))?;
+                streamers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|login| !login.is_empty())
+                    .map(|login| ChannelConfig::new(login.to_string()))
+                    .collect()
+            }
+        };
+        if channels.is_empty() {
+            return Err("No channels configured".into());
+        }
+        if let Some(channel) = channels
+            .iter()
+            .find(|c| c.prefix.is_empty() || c.prefix.contains(char::is_whitespace))
+        {
+            return Err(format!(
+                "Channel {} has command prefix {:?}, it must be non-empty without whitespace",
+                channel.login, channel.prefix
+            )
+            .into());
+        }
+        if let Some(channel) = channels
+            .iter()
+            .find(|c| c.nft_package.is_some() && c.nft_minters.is_none())
+        {
+            return Err(format!(
+                "Channel {} sets nft_package without the nft_minters of that package",
+                channel.login
+            )
+            .into());
+        }
+        let channels = channels
+            .into_iter()
+            .map(|mut channel| {
+                channel.login = channel.login.to_lowercase();
+                (channel.login.clone(), channel)
+            })
+            .collect();
+        Ok(Self { channels })
+    }
+
+    pub fn get(&self, login: &str) -> Option<&ChannelConfig> {
+        self.channels.get(&login.to_lowercase())
+    }
+
+    pub fn logins(&self) -> impl Iterator<Item = &String> {
+        self.channels.keys()
+    }
+}
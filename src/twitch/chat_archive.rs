@@ -0,0 +1,82 @@
+use crate::GlobalError;
+use crate::pg::PgClient;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use twitch_irc::message::PrivmsgMessage;
+
+/// Archives every chat line of channels with `archive_chat` into the monthly
+/// partitions of `chat_archive`.
+#[derive(Default)]
+pub struct ChatArchive {
+    /// Months whose partition was already created, e.g. `2025-06`.
+    partitions: HashSet<String>,
+}
+
+impl ChatArchive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn archive(
+        &mut self,
+        client: &PgClient,
+        priv_msg: &PrivmsgMessage,
+    ) -> Result<(), GlobalError> {
+        let sent_at = priv_msg.server_timestamp.naive_utc();
+        let month = sent_at.format("%Y-%m").to_string();
+        if !self.partitions.contains(&month) {
+            client
+                .execute("SELECT ensure_chat_archive_partition($1)", &[&sent_at])
+                .await?;
+            self.partitions.insert(month);
+        }
+
+        let badges: Vec<Value> = priv_msg
+            .badges
+            .iter()
+            .map(|badge| json!({"name": badge.name, "version": badge.version}))
+            .collect();
+        let emotes: Vec<Value> = priv_msg
+            .emotes
+            .iter()
+            .map(|emote| {
+                json!({
+                    "id": emote.id,
+                    "code": emote.code,
+                    "start": emote.char_range.start,
+                    "end": emote.char_range.end,
+                })
+            })
+            .collect();
+        let tags = priv_msg
+            .source
+            .tags
+            .0
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), serde_json::to_value(value)?)))
+            .collect::<Result<Map<String, Value>, serde_json::Error>>()?;
+
+        // reconnects can replay a message, the first copy is kept
+        let query = "INSERT INTO chat_archive (twitch_message_id, channel_id, channel_login, sender_id, sender_login, text, badges, emotes, tags, sent_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+             ON CONFLICT (twitch_message_id, sent_at) DO NOTHING";
+        client
+            .execute(
+                query,
+                &[
+                    &priv_msg.message_id,
+                    &priv_msg.channel_id.parse::<i64>()?,
+                    &priv_msg.channel_login,
+                    &priv_msg.sender.id.parse::<i64>()?,
+                    &priv_msg.sender.login,
+                    &priv_msg.message_text,
+                    &Value::Array(badges),
+                    &Value::Array(emotes),
+                    &Value::Object(tags),
+                    &sent_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -1,89 +1,111 @@
-use crate::pg::pg::PgConnect;
-use regex::Regex;
-use std::error::Error;
-use std::fmt::Display;
-use std::str::FromStr;
-use tracing::info;
+use crate::GlobalError;
+use crate::pg::PgClient;
+use crate::twitch::commands::parser::parse_command;
+use uuid::Uuid;
 
-type GlobalError = Box<dyn Error>;
+/// A chat line that invoked a command, e.g. `!STORE hello`.
 #[derive(Debug)]
 pub struct ChatMessage {
-    command: ChatCommands,
-    user_id: i64,
+    /// Upper-cased command name without the prefix, e.g. `STORE`.
+    pub command: String,
+    /// Raw arguments after the command, empty for a bare `!NFT`.
+    pub text: String,
+    pub user_id: i64,
+    pub channel_id: i64,
+    /// IRC `id` tag, referenced by `CLEARMSG` when a moderator deletes the message.
+    pub message_id: String,
 }
 
-#[derive(Debug)]
-enum ChatCommands {
-    STORE_CHAT_MESSAGE(String),
-    CLAIM_NFT(String),
-    Unknown(String),
-}
-impl Display for ChatCommands {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            ChatCommands::STORE_CHAT_MESSAGE(s) => "!STORE".to_string(),
-            ChatCommands::CLAIM_NFT(s) => "!NFT".to_string(),
-            ChatCommands::Unknown(s) => "UNKNOWN".to_string(),
-        };
-        write!(f, "{}", str)
+impl ChatMessage {
+    /// Returns `None` for ordinary chat lines that are not commands.
+    pub fn new(
+        prefix: &str,
+        full_message: &str,
+        user_id: i64,
+        channel_id: i64,
+        message_id: &str,
+    ) -> Option<Self> {
+        let parsed = parse_command(prefix, full_message)?;
+
+        Some(Self {
+            command: parsed.name,
+            text: parsed.raw_args,
+            user_id,
+            channel_id,
+            message_id: message_id.to_string(),
+        })
     }
-}
-impl FromStr for ChatCommands {
-    type Err = GlobalError;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let reg = Regex::new(r"^(![A-Z]+)\s(.*)")?;
-        let captures = reg.captures(s).ok_or("Failed to capture")?;
-        // todo clean up unwraps
-        let command = captures.get(1).ok_or("Failed to capture command")?;
-        let text = captures
-            .get(2)
-            .ok_or("Failed to capture text")?
-            .as_str()
-            .to_string();
-        let parsed_command = match command.as_str() {
-            "!STORE" => ChatCommands::STORE_CHAT_MESSAGE(text),
-            "!NFT" => ChatCommands::CLAIM_NFT(text),
-            _ => ChatCommands::Unknown(text),
-        };
-        Ok(parsed_command)
+    /// Value of the `command` column, e.g. `!STORE`.
+    pub fn command_label(&self) -> String {
+        format!("!{}", self.command)
     }
-}
-impl ChatMessage {
-    pub fn new(full_message: String, user_id: i64) -> Self {
-        let command = Self::parse(full_message).unwrap();
 
-        Self { command, user_id }
+    /// Stores the message, linked to the channel's open stream session.
+    pub async fn save(&self, client: &PgClient, channel_login: &str) -> Result<Uuid, GlobalError> {
+        let query = "INSERT INTO chat_messages ( user_id, text, command, channel_id, channel_login, twitch_message_id, stream_session_id ) \
+            VALUES ($1, $2, $3, $4, $5, $6, (SELECT id FROM stream_sessions WHERE broadcaster_id = $4 AND ended_at IS NULL ORDER BY started_at DESC LIMIT 1)) \
+            RETURNING id";
+        let row = client
+            .query_one(
+                query,
+                &[
+                    &self.user_id,
+                    &self.text,
+                    &self.command_label(),
+                    &self.channel_id,
+                    &channel_login,
+                    &self.message_id,
+                ],
+            )
+            .await?;
+        Ok(row.get(0))
     }
 
-    fn parse(full_message: String) -> Result<ChatCommands, GlobalError> {
-        ChatCommands::from_str(&full_message)
+    /// How many times the user ran this command in the channel.
+    pub async fn count_previous(&self, client: &PgClient) -> Result<i64, GlobalError> {
+        let row = client
+            .query_one(
+                "SELECT count(*) FROM chat_messages WHERE channel_id = $1 AND user_id = $2 AND command = $3",
+                &[&self.channel_id, &self.user_id, &self.command_label()],
+            )
+            .await?;
+        Ok(row.get(0))
     }
+}
 
-    pub async fn verify_and_send(&self) -> Result<(), GlobalError> {
-        // let command = self.parse().unwrap_or(ChatCommands::Unknown);
-        let pool = PgConnect::create_pool_from_env()?;
-        let client = pool.get().await?;
-        match &self.command {
-            ChatCommands::STORE_CHAT_MESSAGE(text) => {
-                let query =
-                    "INSERT INTO chat_messages ( user_id, text, command ) VALUES ($1, $2, $3)";
-                client
-                    .query(query, &[&self.user_id, text, &self.command.to_string()])
-                    .await?;
-            }
-            ChatCommands::CLAIM_NFT(text) => {
-                let query =
-                    "INSERT INTO chat_messages (user_id, text, command) VALUES ($1, $2, $3)";
-                client
-                    .query(query, &[&self.user_id, text, &self.command.to_string()])
-                    .await?;
-            }
-            ChatCommands::Unknown(text) => {
-                info!("Skipping message {}", text);
-            }
-        };
+/// Marks stored chat messages as removed by a moderator, so they are not minted.
+pub struct Moderation;
+
+impl Moderation {
+    /// Marks the message a moderator deleted, returns the ids of the marked rows.
+    pub async fn moderate_message(
+        client: &PgClient,
+        twitch_message_id: &str,
+    ) -> Result<Vec<Uuid>, GlobalError> {
+        let rows = client
+            .query(
+                "UPDATE chat_messages SET moderated_at = now() \
+                 WHERE twitch_message_id = $1 AND moderated_at IS NULL RETURNING id",
+                &[&twitch_message_id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
 
-        Ok(())
+    /// Marks every message of a banned or timed out user in the channel.
+    pub async fn moderate_user(
+        client: &PgClient,
+        channel_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<Uuid>, GlobalError> {
+        let rows = client
+            .query(
+                "UPDATE chat_messages SET moderated_at = now() \
+                 WHERE channel_id = $1 AND user_id = $2 AND moderated_at IS NULL RETURNING id",
+                &[&channel_id, &user_id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
     }
 }
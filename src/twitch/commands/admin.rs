@@ -0,0 +1,213 @@
+//! Moderator commands that control the claim pipeline of a channel.
+
+use crate::claims::pipeline::PipelineState;
+use crate::claims::wallets::Wallets;
+use crate::claims::{ClaimJob, ClaimSource, NewClaim};
+use crate::twitch::commands::{
+    ArgKind, ArgSpec, CommandContext, CommandError, CommandHandler, Rejected, Reply, Role,
+};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use tracing::info;
+
+const USER_ARG: &[ArgSpec] = &[ArgSpec {
+    name: "user",
+    kind: ArgKind::Mention,
+    required: true,
+}];
+
+const MIST_PER_SUI: f64 = 1_000_000_000.0;
+
+/// `!PAUSE` stops minting the channel's claims, new claims are still queued.
+pub struct PauseCommand;
+
+impl CommandHandler for PauseCommand {
+    fn name(&self) -> &'static str {
+        "PAUSE"
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let client = ctx.pool.get().await?;
+            PipelineState::set_paused(&client, ctx.message.channel_id, true, ctx.message.user_id)
+                .await?;
+            info!("Minting paused in channel {}", ctx.channel.login);
+            Ok(Some("Minting paused".to_string()))
+        }
+        .boxed()
+    }
+}
+
+/// `!RESUME` continues minting after a `!PAUSE`.
+pub struct ResumeCommand;
+
+impl CommandHandler for ResumeCommand {
+    fn name(&self) -> &'static str {
+        "RESUME"
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let client = ctx.pool.get().await?;
+            PipelineState::set_paused(&client, ctx.message.channel_id, false, ctx.message.user_id)
+                .await?;
+            info!("Minting resumed in channel {}", ctx.channel.login);
+            Ok(Some("Minting resumed".to_string()))
+        }
+        .boxed()
+    }
+}
+
+/// `!AIRDROP @user` queues a mint to the user's registered wallet.
+pub struct AirdropCommand;
+
+impl CommandHandler for AirdropCommand {
+    fn name(&self) -> &'static str {
+        "AIRDROP"
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        USER_ARG
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn mints(&self) -> bool {
+        true
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let login = ctx.args.mention("user").ok_or("Missing user")?;
+            let client = ctx.pool.get().await?;
+            let Some(user) = ctx.twitch.resolve_login(&client, login).await? else {
+                return Err(Rejected(Some(format!("Unknown user @{}", login))).into());
+            };
+            let user_id = user.user_id()?;
+            let Some(recipient) = Wallets::get(&client, user_id).await? else {
+                return Err(Rejected(Some(format!(
+                    "@{} has no wallet, they can register one with !NFT <address>",
+                    user.display_name
+                )))
+                .into());
+            };
+            let claim = NewClaim {
+                chat_message_id: None,
+                channel_id: ctx.message.channel_id,
+                channel_login: &ctx.channel.login,
+                user_id,
+                recipient,
+                source: ClaimSource::Airdrop,
+                reward: None,
+            };
+            let job_id = ClaimJob::enqueue(&client, &claim).await?;
+            info!(
+                "User {} airdropped claim {} to {}",
+                ctx.message.user_id, job_id, user.login
+            );
+            Ok(Some(format!("Airdrop to @{} queued", user.display_name)))
+        }
+        .boxed()
+    }
+}
+
+/// `!REVOKE @user` drops the user's claims that were not minted yet.
+pub struct RevokeCommand;
+
+impl CommandHandler for RevokeCommand {
+    fn name(&self) -> &'static str {
+        "REVOKE"
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        USER_ARG
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let login = ctx.args.mention("user").ok_or("Missing user")?;
+            let client = ctx.pool.get().await?;
+            let Some(user) = ctx.twitch.resolve_login(&client, login).await? else {
+                return Err(Rejected(Some(format!("Unknown user @{}", login))).into());
+            };
+            let revoked =
+                ClaimJob::revoke_pending(&client, ctx.message.channel_id, user.user_id()?).await?;
+            info!(
+                "User {} revoked {} claim(s) of {}",
+                ctx.message.user_id, revoked, user.login
+            );
+            Ok(Some(format!(
+                "Revoked {} pending claim(s) of @{}",
+                revoked, user.display_name
+            )))
+        }
+        .boxed()
+    }
+}
+
+/// `!STATUS` reports whether minting is paused, the queue depth and the oracle's balance.
+pub struct StatusCommand;
+
+impl CommandHandler for StatusCommand {
+    fn name(&self) -> &'static str {
+        "STATUS"
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let client = ctx.pool.get().await?;
+            let paused = PipelineState::is_paused(&client, ctx.message.channel_id).await?;
+            let depth = ClaimJob::queue_depth(&client, ctx.message.channel_id).await?;
+            let balance = match ctx.oracle {
+                Some(oracle) => match oracle.balance().await {
+                    Ok(mist) => format!("{:.3} SUI", mist as f64 / MIST_PER_SUI),
+                    Err(e) => {
+                        info!("Failed to read the oracle balance: {:?}", e);
+                        "unknown".to_string()
+                    }
+                },
+                None => "no wallet".to_string(),
+            };
+            Ok(Some(format!(
+                "Minting {} | {} claim(s) queued | oracle balance {}",
+                if paused { "paused" } else { "running" },
+                depth,
+                balance
+            )))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,133 @@
+use crate::pg::PgClient;
+use crate::twitch::commands::CommandError;
+use deadpool_postgres::Transaction;
+use serde::Deserialize;
+
+/// Cooldowns of a command in a channel, zero disables one.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CooldownConfig {
+    /// Between two uses of the command by the same user.
+    #[serde(default)]
+    pub per_user_secs: i64,
+    /// Between two uses of the command by anyone.
+    #[serde(default)]
+    pub per_command_secs: i64,
+    /// Between two uses of any command in the channel.
+    #[serde(default)]
+    pub global_secs: i64,
+}
+
+/// Name of the token bucket shared by every command that mints.
+pub const MINT_BUCKET: &str = "mints";
+
+/// Cooldowns and the token a command took, so they can be given back when
+/// the command is rejected.
+#[derive(Debug, Default)]
+pub struct Lease {
+    /// Scopes that were taken, with the epoch of their previous use or `None`
+    /// when they were used for the first time.
+    scopes: Vec<(String, Option<f64>)>,
+    /// Bucket a token was taken from, with its size.
+    token: Option<(&'static str, f64)>,
+}
+
+pub struct Cooldowns;
+
+impl Cooldowns {
+    /// Takes the user's, the command's and the channel's cooldown in `transaction`.
+    /// Returns `None` if one of them is still running, the caller then rolls
+    /// back so that none is taken.
+    pub async fn try_acquire(
+        transaction: &Transaction<'_>,
+        config: &CooldownConfig,
+        channel_id: i64,
+        command: &str,
+        user_id: i64,
+    ) -> Result<Option<Lease>, CommandError> {
+        let scopes = [
+            (
+                format!("user:{}:{}:{}", channel_id, command, user_id),
+                config.per_user_secs,
+            ),
+            (
+                format!("command:{}:{}", channel_id, command),
+                config.per_command_secs,
+            ),
+            (format!("global:{}", channel_id), config.global_secs),
+        ];
+        // the subquery reads the row as it was before this statement
+        let query = "INSERT INTO command_cooldowns (scope, last_used_at) VALUES ($1, localtimestamp) \
+             ON CONFLICT (scope) DO UPDATE SET last_used_at = excluded.last_used_at \
+             WHERE command_cooldowns.last_used_at <= localtimestamp - ($2::bigint * interval '1 second') \
+             RETURNING (SELECT extract(epoch from c.last_used_at)::float8 FROM command_cooldowns c WHERE c.scope = $1)";
+        let mut lease = Lease::default();
+        for (scope, secs) in scopes.into_iter().filter(|(_, secs)| *secs > 0) {
+            match transaction.query_opt(query, &[&scope, &secs]).await? {
+                Some(row) => lease.scopes.push((scope, row.get(0))),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(lease))
+    }
+
+    /// Takes a token from the bucket `name`, which holds up to `per_minute`
+    /// tokens and is refilled continuously, and adds it to `lease`. Returns `false`
+    /// when it is empty. A bucket always holds at least one token, so `per_minute`
+    /// below 1 is raised to 1.
+    pub async fn try_take_token(
+        transaction: &Transaction<'_>,
+        lease: &mut Lease,
+        name: &'static str,
+        per_minute: f64,
+    ) -> Result<bool, CommandError> {
+        let per_minute = per_minute.max(1.0);
+        let query = "INSERT INTO rate_limit_buckets (name, tokens, updated_at) VALUES ($1, $2::float8 - 1, localtimestamp) \
+             ON CONFLICT (name) DO UPDATE \
+             SET tokens = LEAST($2::float8, rate_limit_buckets.tokens + EXTRACT(EPOCH FROM (localtimestamp - rate_limit_buckets.updated_at))::float8 * $2::float8 / 60.0) - 1, \
+                 updated_at = localtimestamp \
+             WHERE LEAST($2::float8, rate_limit_buckets.tokens + EXTRACT(EPOCH FROM (localtimestamp - rate_limit_buckets.updated_at))::float8 * $2::float8 / 60.0) >= 1 \
+             RETURNING tokens";
+        if transaction
+            .query_opt(query, &[&name, &per_minute])
+            .await?
+            .is_none()
+        {
+            return Ok(false);
+        }
+        lease.token = Some((name, per_minute));
+        Ok(true)
+    }
+
+    /// Gives back what `lease` took: each cooldown is reset to its previous use
+    /// and the token is returned to its bucket.
+    pub async fn give_back(client: &PgClient, lease: &Lease) -> Result<(), CommandError> {
+        for (scope, previous) in &lease.scopes {
+            match previous {
+                Some(epoch) => {
+                    client
+                        .execute(
+                            "UPDATE command_cooldowns \
+                             SET last_used_at = 'epoch'::timestamp + $2::float8 * interval '1 second' \
+                             WHERE scope = $1",
+                            &[scope, epoch],
+                        )
+                        .await?;
+                }
+                None => {
+                    client
+                        .execute("DELETE FROM command_cooldowns WHERE scope = $1", &[scope])
+                        .await?;
+                }
+            }
+        }
+        if let Some((name, per_minute)) = lease.token {
+            client
+                .execute(
+                    "UPDATE rate_limit_buckets SET tokens = LEAST($2::float8, tokens + 1) WHERE name = $1",
+                    &[&name, &per_minute],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
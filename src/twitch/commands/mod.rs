@@ -0,0 +1,505 @@
+pub mod admin;
+pub mod cooldown;
+pub mod nft;
+pub mod parser;
+pub mod roles;
+pub mod store;
+
+pub use roles::{Role, Roles};
+
+use crate::config::env_opt;
+use crate::sui::Oracle;
+use crate::twitch::TwitchApi;
+use crate::twitch::channel_config::ChannelConfig;
+use crate::twitch::chat_message::ChatMessage;
+use crate::twitch::commands::cooldown::{Cooldowns, MINT_BUCKET};
+use crate::twitch::commands::parser::{Args, parse_args};
+use deadpool_postgres::Pool;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub type CommandError = crate::GlobalError;
+/// Message a handler wants to send back into the chat.
+pub type Reply = Option<String>;
+
+/// Returned by a handler that refuses the command, e.g. a claim over the
+/// channel's limit. The cooldowns and the mint token taken for it are given back,
+/// so a handler rejects before it writes anything.
+#[derive(Debug)]
+pub struct Rejected(pub Reply);
+
+impl Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(reply) => write!(f, "Command rejected: {}", reply),
+            None => write!(f, "Command rejected"),
+        }
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Free text, takes the rest of the message.
+    Text,
+    /// A single word or quoted string.
+    Word,
+    /// Sui address, e.g. `0x0b35...6091`.
+    Address,
+    Integer,
+    /// Twitch login, with or without the `@`.
+    Mention,
+}
+
+impl Display for ArgKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ArgKind::Text => "text",
+            ArgKind::Word => "a word",
+            ArgKind::Address => "a Sui address",
+            ArgKind::Integer => "an integer",
+            ArgKind::Mention => "an @user",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// One argument of a command, used for validation and usage hints.
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+/// Shared clients the commands work with.
+#[derive(Clone)]
+pub struct CommandServices {
+    pub pool: Pool,
+    pub twitch: Arc<TwitchApi>,
+    /// `None` when the oracle runs without a Sui wallet.
+    pub oracle: Option<Arc<Oracle>>,
+}
+
+/// Outcome of a chat line that looked like a command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    Ignored,
+    Handled(Reply),
+}
+
+/// Everything a handler knows about the chat message that invoked it.
+/// `args` are already validated against [`CommandHandler::args`].
+pub struct CommandContext<'a> {
+    pub pool: &'a Pool,
+    pub twitch: &'a TwitchApi,
+    pub oracle: Option<&'a Oracle>,
+    pub channel: &'a ChannelConfig,
+    pub message: &'a ChatMessage,
+    pub args: Args,
+    pub sender_roles: &'a Roles,
+}
+
+/// A chat command. Implementations live in their own module and are
+/// registered in [`CommandRegistry::with_defaults`].
+pub trait CommandHandler: Send + Sync {
+    /// Upper-case name without the prefix, e.g. `NFT` for `!NFT`.
+    fn name(&self) -> &'static str;
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        &[]
+    }
+
+    fn required_role(&self) -> Role {
+        Role::Viewer
+    }
+
+    /// Whether the command queues a mint and takes from the mint rate limit.
+    fn mints(&self) -> bool {
+        false
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>>;
+
+    fn usage(&self) -> String {
+        let args: Vec<String> = self
+            .args()
+            .iter()
+            .map(|arg| match arg.required {
+                true => format!("<{}>", arg.name),
+                false => format!("[{}]", arg.name),
+            })
+            .collect();
+        format!("!{} {}", self.name(), args.join(" "))
+            .trim_end()
+            .to_string()
+    }
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    handlers: Vec<Arc<dyn CommandHandler>>,
+    /// Names and aliases pointing into `handlers`.
+    lookup: HashMap<String, usize>,
+    /// Mints allowed per minute across all channels, unlimited when `None`.
+    mints_per_minute: Option<f64>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with every command shipped with the oracle.
+    /// `MINTS_PER_MINUTE` limits how many mints the commands can queue.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.mints_per_minute = env_opt::<f64>("MINTS_PER_MINUTE").map(|per_minute| {
+            if per_minute < 1.0 {
+                warn!("MINTS_PER_MINUTE={} is below 1, using 1", per_minute);
+            }
+            per_minute.max(1.0)
+        });
+        registry.register(store::StoreCommand);
+        registry.register(nft::NftCommand);
+        registry.register(admin::PauseCommand);
+        registry.register(admin::ResumeCommand);
+        registry.register(admin::AirdropCommand);
+        registry.register(admin::RevokeCommand);
+        registry.register(admin::StatusCommand);
+        registry
+    }
+
+    pub fn register(&mut self, handler: impl CommandHandler + 'static) {
+        let index = self.handlers.len();
+        self.lookup.insert(handler.name().to_uppercase(), index);
+        for alias in handler.aliases() {
+            self.lookup.insert(alias.to_uppercase(), index);
+        }
+        self.handlers.push(Arc::new(handler));
+    }
+
+    /// Finds a command by name or alias, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&Arc<dyn CommandHandler>> {
+        self.lookup
+            .get(&name.to_uppercase())
+            .map(|index| &self.handlers[*index])
+    }
+
+    /// Runs the handler of the message's command.
+    pub async fn dispatch(
+        &self,
+        services: &CommandServices,
+        channel: &ChannelConfig,
+        message: &ChatMessage,
+        sender_roles: &Roles,
+    ) -> Result<Dispatch, CommandError> {
+        let Some(handler) = self.find(&message.command) else {
+            info!("Skipping unknown command {}", message.command);
+            return Ok(Dispatch::Ignored);
+        };
+        if !channel.is_enabled(handler.name()) {
+            info!(
+                "Command {} is disabled in channel {}",
+                handler.name(),
+                channel.login
+            );
+            return Ok(Dispatch::Ignored);
+        }
+        let required_role = channel
+            .required_role(handler.name())
+            .unwrap_or(handler.required_role());
+        if !sender_roles.satisfies(required_role) {
+            info!(
+                "User {} is a {}, {} requires {}",
+                message.user_id,
+                sender_roles.highest(),
+                handler.name(),
+                required_role
+            );
+            return Ok(Dispatch::Ignored);
+        }
+        let args = match parse_args(handler.args(), &message.text) {
+            Ok(args) => args,
+            Err(e) => {
+                info!(
+                    "Wrong usage of {}: {}, expected {}",
+                    handler.name(),
+                    e,
+                    handler.usage()
+                );
+                return Ok(Dispatch::Ignored);
+            }
+        };
+        // cooldowns and the mint token are committed before the handler runs, so
+        // its network calls don't hold the rows locked, and given back on rejection
+        let lease = {
+            let mut client = services.pool.get().await?;
+            let transaction = client.transaction().await?;
+            let cooldown = channel.cooldown(handler.name());
+            let Some(mut lease) = Cooldowns::try_acquire(
+                &transaction,
+                &cooldown,
+                message.channel_id,
+                handler.name(),
+                message.user_id,
+            )
+            .await?
+            else {
+                transaction.rollback().await?;
+                info!(
+                    "Command {} of user {} is on cooldown",
+                    handler.name(),
+                    message.user_id
+                );
+                return Ok(Dispatch::Ignored);
+            };
+            if let Some(per_minute) = self.mints_per_minute
+                && handler.mints()
+                && !Cooldowns::try_take_token(&transaction, &mut lease, MINT_BUCKET, per_minute)
+                    .await?
+            {
+                transaction.rollback().await?;
+                info!("Mint rate limit reached, skipping {}", handler.name());
+                return Ok(Dispatch::Ignored);
+            }
+            transaction.commit().await?;
+            lease
+        };
+
+        let ctx = CommandContext {
+            pool: &services.pool,
+            twitch: &services.twitch,
+            oracle: services.oracle.as_deref(),
+            channel,
+            message,
+            args,
+            sender_roles,
+        };
+        match handler.handle(&ctx).await {
+            Ok(reply) => Ok(Dispatch::Handled(reply)),
+            // any other error keeps the cooldowns, the handler may have written already
+            Err(e) => {
+                let rejected = e.downcast::<Rejected>()?;
+                let client = services.pool.get().await?;
+                Cooldowns::give_back(&client, &lease).await?;
+                info!(
+                    "Command {} of user {} was rejected",
+                    handler.name(),
+                    message.user_id
+                );
+                Ok(Dispatch::Handled(rejected.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::twitch::commands::parser::ParseError;
+    use futures::FutureExt;
+
+    const ADDRESS: &str = "0x0b3584c8e885957e7dbcecc56bb8a8103cc5e63b9c81bfe219fdff85bbad6091";
+
+    struct GiveawayCommand;
+
+    impl CommandHandler for GiveawayCommand {
+        fn name(&self) -> &'static str {
+            "GIVEAWAY"
+        }
+
+        fn aliases(&self) -> &'static [&'static str] {
+            &["ga", "Raffle"]
+        }
+
+        fn args(&self) -> &'static [ArgSpec] {
+            &[
+                ArgSpec {
+                    name: "winners",
+                    kind: ArgKind::Integer,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "prize",
+                    kind: ArgKind::Word,
+                    required: false,
+                },
+            ]
+        }
+
+        fn handle<'a>(
+            &'a self,
+            _ctx: &'a CommandContext<'a>,
+        ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+            async { Ok(None) }.boxed()
+        }
+    }
+
+    fn registry() -> CommandRegistry {
+        let mut registry = CommandRegistry::with_defaults();
+        registry.register(GiveawayCommand);
+        registry
+    }
+
+    fn handler(name: &str) -> Arc<dyn CommandHandler> {
+        registry()
+            .find(name)
+            .expect("Command is registered")
+            .clone()
+    }
+
+    fn parse(name: &str, raw_args: &str) -> Result<Args, ParseError> {
+        parse_args(handler(name).args(), raw_args)
+    }
+
+    #[test]
+    fn commands_are_found_by_name_ignoring_case() {
+        let registry = registry();
+        for name in [
+            "STORE", "NFT", "PAUSE", "RESUME", "AIRDROP", "REVOKE", "STATUS",
+        ] {
+            assert_eq!(registry.find(name).unwrap().name(), name);
+            assert_eq!(registry.find(&name.to_lowercase()).unwrap().name(), name);
+        }
+        assert!(registry.find("UNKNOWN").is_none());
+        assert!(registry.find("").is_none());
+    }
+
+    #[test]
+    fn aliases_resolve_to_their_command() {
+        let registry = registry();
+        for name in ["GIVEAWAY", "ga", "GA", "raffle", "RAFFLE"] {
+            assert_eq!(registry.find(name).unwrap().name(), "GIVEAWAY");
+        }
+    }
+
+    #[test]
+    fn usage_lists_the_arguments() {
+        assert_eq!(handler("ga").usage(), "!GIVEAWAY <winners> [prize]");
+        assert_eq!(handler("nft").usage(), "!NFT [address]");
+        assert_eq!(handler("status").usage(), "!STATUS");
+    }
+
+    #[test]
+    fn channels_enable_commands_with_or_without_prefix() {
+        let mut channel = ChannelConfig::new("Andiskey".to_string());
+        assert_eq!(channel.login, "andiskey");
+        assert!(channel.is_enabled("STORE"));
+        assert!(channel.is_enabled("!store"));
+        assert!(!channel.is_enabled("GIVEAWAY"));
+
+        channel.commands = vec!["nft".to_string(), "!Giveaway".to_string()];
+        assert!(channel.is_enabled("NFT"));
+        assert!(channel.is_enabled("GIVEAWAY"));
+        assert!(!channel.is_enabled("STORE"));
+    }
+
+    #[test]
+    fn channels_override_the_required_role() {
+        let mut channel = ChannelConfig::new("andiskey".to_string());
+        assert_eq!(channel.required_role("STORE"), None);
+
+        channel
+            .command_roles
+            .insert("!store".to_string(), Role::Subscriber);
+        channel
+            .command_roles
+            .insert("STATUS".to_string(), Role::Viewer);
+        assert_eq!(channel.required_role("STORE"), Some(Role::Subscriber));
+        assert_eq!(channel.required_role("!Status"), Some(Role::Viewer));
+        assert_eq!(channel.required_role("NFT"), None);
+    }
+
+    #[test]
+    fn roles_satisfy_lower_requirements() {
+        assert_eq!(handler("STORE").required_role(), Role::Viewer);
+        assert_eq!(handler("PAUSE").required_role(), Role::Moderator);
+
+        let moderator = Roles::new(vec![Role::Subscriber, Role::Moderator]);
+        assert!(moderator.satisfies(Role::Moderator));
+        assert!(moderator.satisfies(Role::Viewer));
+        assert!(!moderator.satisfies(Role::Broadcaster));
+        assert_eq!(moderator.highest(), Role::Moderator);
+
+        let viewer = Roles::new(Vec::new());
+        assert!(viewer.satisfies(Role::Viewer));
+        assert!(!viewer.satisfies(Role::Subscriber));
+    }
+
+    #[test]
+    fn store_takes_free_text() {
+        assert_eq!(
+            parse("STORE", "I'm  here").unwrap().text("text"),
+            Some("I'm  here")
+        );
+        assert_eq!(parse("STORE", ""), Err(ParseError::MissingArgument("text")));
+    }
+
+    #[test]
+    fn nft_takes_an_optional_address() {
+        assert!(parse("NFT", "").unwrap().address("address").is_none());
+        assert!(parse("NFT", ADDRESS).unwrap().address("address").is_some());
+        assert!(matches!(
+            parse("NFT", "my-wallet"),
+            Err(ParseError::InvalidArgument {
+                name: "address",
+                ..
+            })
+        ));
+        assert_eq!(
+            parse("NFT", &format!("{} {}", ADDRESS, ADDRESS)),
+            Err(ParseError::TooManyArguments(1))
+        );
+    }
+
+    #[test]
+    fn user_commands_take_one_mention() {
+        for name in ["AIRDROP", "REVOKE"] {
+            assert_eq!(
+                parse(name, "@AndISkey").unwrap().mention("user"),
+                Some("andiskey")
+            );
+            assert_eq!(
+                parse(name, "andiskey").unwrap().mention("user"),
+                Some("andiskey")
+            );
+            assert_eq!(parse(name, ""), Err(ParseError::MissingArgument("user")));
+            assert!(matches!(
+                parse(name, "@not-a-login"),
+                Err(ParseError::InvalidArgument { name: "user", .. })
+            ));
+            assert_eq!(parse(name, "@a @b"), Err(ParseError::TooManyArguments(1)));
+        }
+    }
+
+    #[test]
+    fn pipeline_commands_take_no_arguments() {
+        for name in ["PAUSE", "RESUME", "STATUS"] {
+            assert_eq!(parse(name, "").unwrap(), Args::default());
+            assert_eq!(parse(name, "now"), Err(ParseError::TooManyArguments(1)));
+        }
+    }
+
+    #[test]
+    fn only_claim_commands_mint() {
+        let minting: Vec<&str> = [
+            "STORE", "NFT", "PAUSE", "RESUME", "AIRDROP", "REVOKE", "STATUS",
+        ]
+        .into_iter()
+        .filter(|name| handler(name).mints())
+        .collect();
+        assert_eq!(minting, vec!["NFT", "AIRDROP"]);
+    }
+}
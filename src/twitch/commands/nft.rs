@@ -0,0 +1,77 @@
+use crate::claims::wallets::Wallets;
+use crate::claims::{ClaimJob, ClaimSource, NewClaim};
+use crate::twitch::commands::{
+    ArgKind, ArgSpec, CommandContext, CommandError, CommandHandler, Rejected, Reply,
+};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use tracing::info;
+
+/// `!NFT [address]` claims the channel's NFT. The address is remembered,
+/// so later claims can leave it out.
+pub struct NftCommand;
+
+impl CommandHandler for NftCommand {
+    fn name(&self) -> &'static str {
+        "NFT"
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        &[ArgSpec {
+            name: "address",
+            kind: ArgKind::Address,
+            required: false,
+        }]
+    }
+
+    fn mints(&self) -> bool {
+        true
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let client = ctx.pool.get().await?;
+            if let Some(max_claims) = ctx.channel.max_claims_per_user
+                && ctx.message.count_previous(&client).await? >= max_claims
+            {
+                info!(
+                    "User {} reached the claim limit in channel {}",
+                    ctx.message.user_id, ctx.channel.login
+                );
+                return Err(Rejected(None).into());
+            }
+            let recipient = match ctx.args.address("address") {
+                Some(address) => {
+                    Wallets::set(&client, ctx.message.user_id, &address).await?;
+                    address
+                }
+                None => match Wallets::get(&client, ctx.message.user_id).await? {
+                    Some(address) => address,
+                    None => {
+                        return Err(Rejected(Some(
+                            "Send your Sui address first: !NFT <address>".to_string(),
+                        ))
+                        .into());
+                    }
+                },
+            };
+            let chat_message_id = ctx.message.save(&client, &ctx.channel.login).await?;
+            let claim = NewClaim {
+                chat_message_id: Some(chat_message_id),
+                channel_id: ctx.message.channel_id,
+                channel_login: &ctx.channel.login,
+                user_id: ctx.message.user_id,
+                recipient,
+                source: ClaimSource::Chat,
+                reward: None,
+            };
+            let job_id = ClaimJob::enqueue(&client, &claim).await?;
+            info!("Queued claim {} to {}", job_id, recipient);
+            Ok(None)
+        }
+        .boxed()
+    }
+}
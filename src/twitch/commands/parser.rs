@@ -0,0 +1,412 @@
+use crate::twitch::commands::{ArgKind, ArgSpec};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use sui_sdk::types::base_types::SuiAddress;
+
+/// A chat line that starts with the command prefix, before its arguments are typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand {
+    /// Upper-cased command name, so `!nft` and `!NFT` are the same command.
+    pub name: String,
+    /// Everything after the name, as typed by the user.
+    pub raw_args: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnterminatedQuote,
+    MissingArgument(&'static str),
+    InvalidArgument {
+        name: &'static str,
+        kind: ArgKind,
+        value: String,
+    },
+    TooManyArguments(usize),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote => write!(f, "Unterminated quote"),
+            ParseError::MissingArgument(name) => write!(f, "Missing argument <{}>", name),
+            ParseError::InvalidArgument { name, kind, value } => {
+                write!(f, "Argument <{}> must be {}, got {:?}", name, kind, value)
+            }
+            ParseError::TooManyArguments(extra) => write!(f, "{} unexpected argument(s)", extra),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue {
+    Text(String),
+    Address(SuiAddress),
+    Integer(i64),
+    /// Lower-cased login without the `@`.
+    Mention(String),
+}
+
+/// Typed arguments of a command invocation, keyed by [`ArgSpec::name`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Args {
+    values: HashMap<&'static str, ArgValue>,
+}
+
+impl Args {
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name)? {
+            ArgValue::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn address(&self, name: &str) -> Option<SuiAddress> {
+        match self.values.get(name)? {
+            ArgValue::Address(address) => Some(*address),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name)? {
+            ArgValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn mention(&self, name: &str) -> Option<&str> {
+        match self.values.get(name)? {
+            ArgValue::Mention(login) => Some(login),
+            _ => None,
+        }
+    }
+}
+
+/// Splits `line` into a command name and its raw arguments.
+/// Returns `None` when the line is ordinary chat or `prefix` is empty.
+pub fn parse_command(prefix: &str, line: &str) -> Option<ParsedCommand> {
+    if prefix.is_empty() {
+        return None;
+    }
+    let rest = line.trim().strip_prefix(prefix)?;
+    let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let name = &rest[..name_end];
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some(ParsedCommand {
+        name: name.to_uppercase(),
+        raw_args: rest[name_end..].trim().to_string(),
+    })
+}
+
+/// Reads arguments one whitespace separated token at a time, so free text
+/// can take the rest of the line untouched. A token starting with `"` is
+/// quoted up to the next unescaped `"`, a backslash escapes the next
+/// character inside quotes. Quotes anywhere else, e.g. in `I'm`, are text.
+pub struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            rest: input.trim_start(),
+        }
+    }
+
+    /// The input after the tokens read so far, as typed.
+    pub fn remainder(&self) -> &'a str {
+        self.rest.trim_end()
+    }
+
+    pub fn next_token(&mut self) -> Result<Option<String>, ParseError> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let mut token = String::new();
+        let mut chars = self.rest.char_indices();
+        let mut end = self.rest.len();
+        if self.rest.starts_with('"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => token.push(escaped),
+                        None => return Err(ParseError::UnterminatedQuote),
+                    },
+                    Some((_, c)) => token.push(c),
+                    None => return Err(ParseError::UnterminatedQuote),
+                }
+            }
+        }
+        for (index, c) in chars {
+            if c.is_whitespace() {
+                end = index;
+                break;
+            }
+            token.push(c);
+        }
+        self.rest = self.rest[end..].trim_start();
+        Ok(Some(token))
+    }
+}
+
+/// Every token of `input`, see [`Tokens`].
+pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = Tokens::new(input);
+    let mut all = Vec::new();
+    while let Some(token) = tokens.next_token()? {
+        all.push(token);
+    }
+    Ok(all)
+}
+
+fn is_login(value: &str) -> bool {
+    (1..=25).contains(&value.len()) && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(spec: &ArgSpec, token: &str) -> Result<ArgValue, ParseError> {
+    let invalid = || ParseError::InvalidArgument {
+        name: spec.name,
+        kind: spec.kind,
+        value: token.to_string(),
+    };
+    match spec.kind {
+        ArgKind::Text | ArgKind::Word => Ok(ArgValue::Text(token.to_string())),
+        ArgKind::Address => SuiAddress::from_str(token)
+            .map(ArgValue::Address)
+            .map_err(|_| invalid()),
+        ArgKind::Integer => token
+            .replace('_', "")
+            .parse()
+            .map(ArgValue::Integer)
+            .map_err(|_| invalid()),
+        ArgKind::Mention => {
+            let login = token.strip_prefix('@').unwrap_or(token);
+            if is_login(login) {
+                Ok(ArgValue::Mention(login.to_lowercase()))
+            } else {
+                Err(invalid())
+            }
+        }
+    }
+}
+
+/// Matches the tokens of `raw_args` against the argument schema of a command.
+pub fn parse_args(specs: &[ArgSpec], raw_args: &str) -> Result<Args, ParseError> {
+    let mut tokens = Tokens::new(raw_args);
+    let mut args = Args::default();
+
+    for spec in specs {
+        if spec.kind == ArgKind::Text {
+            // free text is the rest of the line as typed, quotes and spacing included
+            let text = tokens.remainder();
+            tokens = Tokens::new("");
+            if text.is_empty() {
+                if spec.required {
+                    return Err(ParseError::MissingArgument(spec.name));
+                }
+                continue;
+            }
+            args.values
+                .insert(spec.name, ArgValue::Text(text.to_string()));
+            continue;
+        }
+        match tokens.next_token()? {
+            Some(token) => {
+                args.values.insert(spec.name, parse_value(spec, &token)?);
+            }
+            None if spec.required => return Err(ParseError::MissingArgument(spec.name)),
+            None => {}
+        }
+    }
+    let extra = tokenize(tokens.remainder())?.len();
+    if extra > 0 {
+        return Err(ParseError::TooManyArguments(extra));
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0x0b3584c8e885957e7dbcecc56bb8a8103cc5e63b9c81bfe219fdff85bbad6091";
+
+    const STORE: &[ArgSpec] = &[ArgSpec {
+        name: "text",
+        kind: ArgKind::Text,
+        required: true,
+    }];
+
+    const AIRDROP: &[ArgSpec] = &[
+        ArgSpec {
+            name: "user",
+            kind: ArgKind::Mention,
+            required: true,
+        },
+        ArgSpec {
+            name: "amount",
+            kind: ArgKind::Integer,
+            required: false,
+        },
+        ArgSpec {
+            name: "note",
+            kind: ArgKind::Text,
+            required: false,
+        },
+    ];
+
+    fn command(prefix: &str, line: &str) -> Option<(String, String)> {
+        parse_command(prefix, line).map(|c| (c.name, c.raw_args))
+    }
+
+    #[test]
+    fn commands_need_the_prefix_and_a_name() {
+        assert_eq!(command("!", "hello !nft"), None);
+        assert_eq!(command("!", "!"), None);
+        assert_eq!(command("!", "! nft"), None);
+        assert_eq!(command("!", "!n-f-t"), None);
+        assert_eq!(command("", "nft"), None);
+        assert_eq!(command("?", "!nft"), None);
+        assert_eq!(
+            command("?", "  ?store   hi  there "),
+            Some(("STORE".to_string(), "hi  there".to_string()))
+        );
+        assert_eq!(
+            command("~>", "~>Nft"),
+            Some(("NFT".to_string(), String::new()))
+        );
+    }
+
+    #[test]
+    fn command_names_ignore_case() {
+        for line in ["!nft", "!NFT", "!NfT"] {
+            assert_eq!(command("!", line).unwrap().0, "NFT");
+        }
+        assert_eq!(command("!", "!привет").unwrap().0, "ПРИВЕТ");
+    }
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(
+            tokenize(r#"a "b  c" "" d"#).unwrap(),
+            vec!["a", "b  c", "", "d"]
+        );
+        assert_eq!(tokenize(r#""a b"c d"#).unwrap(), vec!["a bc", "d"]);
+        assert_eq!(tokenize("  ").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn backslash_escapes_inside_quotes() {
+        assert_eq!(tokenize(r#""a \" b""#).unwrap(), vec![r#"a " b"#]);
+        assert_eq!(tokenize(r#""a\\b""#).unwrap(), vec![r"a\b"]);
+        // outside quotes a backslash is an ordinary character
+        assert_eq!(tokenize(r"a\b \x").unwrap(), vec![r"a\b", r"\x"]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_rejected() {
+        assert_eq!(tokenize(r#""abc"#), Err(ParseError::UnterminatedQuote));
+        assert_eq!(tokenize(r#"a "b c\""#), Err(ParseError::UnterminatedQuote));
+        assert_eq!(tokenize(r#""abc\"#), Err(ParseError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn quotes_inside_words_are_text() {
+        assert_eq!(
+            tokenize(r#"I'm 5" tall, 'really'"#).unwrap(),
+            vec!["I'm", "5\"", "tall,", "'really'"]
+        );
+        let args = parse_args(STORE, "I'm here").unwrap();
+        assert_eq!(args.text("text"), Some("I'm here"));
+    }
+
+    #[test]
+    fn free_text_is_kept_as_typed() {
+        let line = r#"  "quoted"   spaced  \n 'single  "#;
+        let args = parse_args(STORE, line).unwrap();
+        assert_eq!(args.text("text"), Some(r#""quoted"   spaced  \n 'single"#));
+        // an unterminated quote is fine in free text
+        let args = parse_args(STORE, r#"say "hi"#).unwrap();
+        assert_eq!(args.text("text"), Some(r#"say "hi"#));
+    }
+
+    #[test]
+    fn arguments_are_typed() {
+        let args = parse_args(AIRDROP, r#"@Foo_1 1_000 thanks for the "raid""#).unwrap();
+        assert_eq!(args.mention("user"), Some("foo_1"));
+        assert_eq!(args.integer("amount"), Some(1000));
+        assert_eq!(args.text("note"), Some(r#"thanks for the "raid""#));
+
+        let args = parse_args(AIRDROP, "foo").unwrap();
+        assert_eq!(args.mention("user"), Some("foo"));
+        assert_eq!(args.integer("amount"), None);
+        assert_eq!(args.text("note"), None);
+
+        let address = [ArgSpec {
+            name: "address",
+            kind: ArgKind::Address,
+            required: false,
+        }];
+        assert_eq!(parse_args(&address, "").unwrap().address("address"), None);
+        assert_eq!(
+            parse_args(&address, ADDRESS).unwrap().address("address"),
+            Some(SuiAddress::from_str(ADDRESS).unwrap())
+        );
+    }
+
+    #[test]
+    fn arity_is_checked() {
+        assert_eq!(
+            parse_args(AIRDROP, ""),
+            Err(ParseError::MissingArgument("user"))
+        );
+        assert_eq!(
+            parse_args(STORE, "   "),
+            Err(ParseError::MissingArgument("text"))
+        );
+        assert_eq!(
+            parse_args(&[], "a \"b c\""),
+            Err(ParseError::TooManyArguments(2))
+        );
+        assert_eq!(parse_args(&[], "").unwrap(), Args::default());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(
+            parse_args(AIRDROP, "@foo abc"),
+            Err(ParseError::InvalidArgument {
+                name: "amount",
+                kind: ArgKind::Integer,
+                value: "abc".to_string(),
+            })
+        );
+        assert!(matches!(
+            parse_args(AIRDROP, "@not-a-login"),
+            Err(ParseError::InvalidArgument { name: "user", .. })
+        ));
+        assert!(matches!(
+            parse_args(AIRDROP, "@"),
+            Err(ParseError::InvalidArgument { name: "user", .. })
+        ));
+        let address = [ArgSpec {
+            name: "address",
+            kind: ArgKind::Address,
+            required: true,
+        }];
+        assert!(matches!(
+            parse_args(&address, "0x12zz"),
+            Err(ParseError::InvalidArgument {
+                name: "address",
+                ..
+            })
+        ));
+    }
+}
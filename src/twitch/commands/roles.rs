@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use std::fmt::Display;
+use twitch_irc::message::Badge;
+
+/// Roles of a chat user. Moderators and the broadcaster pass every check,
+/// VIPs and subscribers only pass their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Role::Viewer => "viewer",
+            Role::Subscriber => "subscriber",
+            Role::Vip => "vip",
+            Role::Moderator => "moderator",
+            Role::Broadcaster => "broadcaster",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Every role the sender of a message has in the channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Roles {
+    roles: Vec<Role>,
+}
+
+impl Roles {
+    pub fn new(roles: Vec<Role>) -> Self {
+        Self { roles }
+    }
+
+    /// Reads the roles from the IRC `badges` tag. The channel owner is
+    /// the broadcaster even when the badge is hidden.
+    pub fn from_badges(badges: &[Badge], is_channel_owner: bool) -> Self {
+        let mut roles: Vec<Role> = badges
+            .iter()
+            .filter_map(|badge| match badge.name.as_str() {
+                "broadcaster" => Some(Role::Broadcaster),
+                "moderator" => Some(Role::Moderator),
+                "vip" => Some(Role::Vip),
+                "subscriber" | "founder" => Some(Role::Subscriber),
+                _ => None,
+            })
+            .collect();
+        if is_channel_owner {
+            roles.push(Role::Broadcaster);
+        }
+        roles.sort();
+        roles.dedup();
+        Self { roles }
+    }
+
+    pub fn has(&self, role: Role) -> bool {
+        role == Role::Viewer || self.roles.contains(&role)
+    }
+
+    pub fn satisfies(&self, required: Role) -> bool {
+        match required {
+            Role::Viewer => true,
+            Role::Subscriber | Role::Vip => {
+                self.has(required) || self.has(Role::Moderator) || self.has(Role::Broadcaster)
+            }
+            Role::Moderator => self.has(Role::Moderator) || self.has(Role::Broadcaster),
+            Role::Broadcaster => self.has(Role::Broadcaster),
+        }
+    }
+
+    /// The most privileged role, for logging.
+    pub fn highest(&self) -> Role {
+        self.roles.last().copied().unwrap_or(Role::Viewer)
+    }
+}
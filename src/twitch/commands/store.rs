@@ -0,0 +1,42 @@
+use crate::twitch::commands::{
+    ArgKind, ArgSpec, CommandContext, CommandError, CommandHandler, Rejected, Reply,
+};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use tracing::info;
+
+/// `!STORE <text>` saves the text of the message.
+pub struct StoreCommand;
+
+impl CommandHandler for StoreCommand {
+    fn name(&self) -> &'static str {
+        "STORE"
+    }
+
+    fn args(&self) -> &'static [ArgSpec] {
+        &[ArgSpec {
+            name: "text",
+            kind: ArgKind::Text,
+            required: true,
+        }]
+    }
+
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a CommandContext<'a>,
+    ) -> BoxFuture<'a, Result<Reply, CommandError>> {
+        async move {
+            let text = ctx.args.text("text").ok_or("Missing text")?;
+            if let Some(max_length) = ctx.channel.max_message_length
+                && text.chars().count() > max_length
+            {
+                info!("Skipping too long message of user {}", ctx.message.user_id);
+                return Err(Rejected(None).into());
+            }
+            let client = ctx.pool.get().await?;
+            ctx.message.save(&client, &ctx.channel.login).await?;
+            Ok(None)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,226 @@
+use crate::twitch::Pagination;
+use futures::{Stream, TryStreamExt, stream};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+pub const HELIX_URL: &str = "https://api.twitch.tv/helix";
+const MAX_RETRIES: u32 = 5;
+
+#[derive(Debug)]
+pub enum HelixError {
+    Http(reqwest::Error),
+    Decode(serde_json::Error),
+    /// Error body returned by Helix, e.g. `{"error":"Unauthorized","status":401,"message":"Invalid OAuth token"}`
+    Api {
+        status: u16,
+        error: String,
+        message: String,
+    },
+    MissingToken,
+    RateLimited,
+}
+
+impl Display for HelixError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HelixError::Http(e) => write!(f, "Helix request failed: {}", e),
+            HelixError::Decode(e) => write!(f, "Failed to decode Helix response: {}", e),
+            HelixError::Api {
+                status,
+                error,
+                message,
+            } => write!(f, "Helix returned {} {}: {}", status, error, message),
+            HelixError::MissingToken => write!(f, "Access token must be presented"),
+            HelixError::RateLimited => write!(f, "Helix rate limit is still exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for HelixError {}
+
+impl From<reqwest::Error> for HelixError {
+    fn from(e: reqwest::Error) -> Self {
+        HelixError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for HelixError {
+    fn from(e: serde_json::Error) -> Self {
+        HelixError::Decode(e)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixErrorBody {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// One page of a Helix collection endpoint.
+#[derive(Debug, Deserialize)]
+pub struct HelixPage<T> {
+    pub data: Vec<T>,
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Debug, Default)]
+struct RateLimit {
+    remaining: Option<u64>,
+    /// Unix timestamp in seconds when the bucket is refilled.
+    reset: Option<u64>,
+}
+
+/// Helix client sharing one HTTP connection pool and one rate limit bucket
+/// between all the calls made with the same credentials.
+#[derive(Clone)]
+pub struct HelixClient {
+    http: Client,
+    base_url: String,
+    client_id: String,
+    access_token: Option<String>,
+    rate_limit: Arc<Mutex<RateLimit>>,
+}
+
+impl HelixClient {
+    pub fn new(http: Client, client_id: String, base_url: String) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client_id,
+            access_token: None,
+            rate_limit: Arc::new(Mutex::new(RateLimit::default())),
+        }
+    }
+
+    pub fn set_access_token(&mut self, access_token: String) {
+        self.access_token = Some(access_token);
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// How long to wait until the bucket is refilled, if it is empty.
+    fn wait_for_bucket(&self) -> Option<Duration> {
+        let rate_limit = self.rate_limit.lock().expect("Rate limit lock poisoned");
+        match (rate_limit.remaining, rate_limit.reset) {
+            (Some(0), Some(reset)) => {
+                let now = Self::now_secs();
+                (reset > now).then(|| Duration::from_secs(reset - now))
+            }
+            _ => None,
+        }
+    }
+
+    fn update_rate_limit(&self, response: &Response) {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        let mut rate_limit = self.rate_limit.lock().expect("Rate limit lock poisoned");
+        if let Some(remaining) = header("Ratelimit-Remaining") {
+            rate_limit.remaining = Some(remaining);
+        }
+        if let Some(reset) = header("Ratelimit-Reset") {
+            rate_limit.reset = Some(reset);
+        }
+    }
+
+    fn request(&self, path: &str, query: &[(&str, String)]) -> Result<RequestBuilder, HelixError> {
+        let access_token = self.access_token.as_ref().ok_or(HelixError::MissingToken)?;
+        let url = format!("{}/{}", self.base_url, path.trim_start_matches('/'));
+        Ok(self
+            .http
+            .get(url)
+            .query(query)
+            .header("Client-ID", &self.client_id)
+            .header("Authorization", format!("Bearer {}", access_token)))
+    }
+
+    /// Sends a GET request to `path`, waiting out the rate limit and
+    /// retrying with backoff when Helix answers with 429.
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<HelixPage<T>, HelixError> {
+        for attempt in 0..MAX_RETRIES {
+            if let Some(wait) = self.wait_for_bucket() {
+                warn!("Helix rate limit exhausted, waiting {:?}", wait);
+                tokio::time::sleep(wait).await;
+            }
+            let response = self.request(path, query)?.send().await?;
+            self.update_rate_limit(&response);
+            let status = response.status();
+            let body = response.text().await?;
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let backoff = self
+                    .wait_for_bucket()
+                    .unwrap_or(Duration::from_secs(1 << attempt));
+                warn!(
+                    "Helix rate limited {}, retrying in {:?} (attempt {})",
+                    path,
+                    backoff,
+                    attempt + 1
+                );
+                tokio::time::sleep(backoff).await;
+                continue;
+            }
+            if !status.is_success() {
+                let parsed =
+                    serde_json::from_str::<HelixErrorBody>(&body).unwrap_or(HelixErrorBody {
+                        error: status.canonical_reason().unwrap_or_default().to_string(),
+                        message: body,
+                    });
+                return Err(HelixError::Api {
+                    status: status.as_u16(),
+                    error: parsed.error,
+                    message: parsed.message,
+                });
+            }
+            return Ok(serde_json::from_str(&body)?);
+        }
+        Err(HelixError::RateLimited)
+    }
+
+    /// Streams every item of a collection endpoint, following `pagination.cursor`.
+    pub fn paginate<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        path: &'a str,
+        query: Vec<(&'a str, String)>,
+    ) -> impl Stream<Item = Result<T, HelixError>> + 'a {
+        // `None` once the last page is read, `Some(None)` before the first one
+        stream::try_unfold(Some(None::<String>), move |cursor| {
+            let mut query = query.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok::<_, HelixError>(None);
+                };
+                if let Some(cursor) = cursor {
+                    query.push(("after", cursor));
+                }
+                let page = self.get::<T>(path, &query).await?;
+                let next = page
+                    .pagination
+                    .and_then(|p| p.cursor)
+                    .filter(|c| !c.is_empty());
+                let items = stream::iter(page.data.into_iter().map(Ok::<T, HelixError>));
+                Ok(Some((items, next.map(Some))))
+            }
+        })
+        .try_flatten()
+    }
+}
@@ -0,0 +1,224 @@
+//! Offline stand-in for `id.twitch.tv` and `api.twitch.tv`, serving the JSON fixtures in `fixtures/twitch`.
+//! Run by the `mock-twitch` binary and started in-process by the integration tests.
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, Vec<String>>,
+}
+
+pub struct MockTwitch {
+    fixtures: PathBuf,
+}
+
+impl MockTwitch {
+    pub fn new(fixtures: impl Into<PathBuf>) -> Self {
+        Self {
+            fixtures: fixtures.into(),
+        }
+    }
+
+    /// Serves requests accepted by `listener` until accepting fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let mock = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = mock.handle(stream).await {
+                    error!("Failed to serve mock request: {:?}", e);
+                }
+            });
+        }
+    }
+
+    fn fixture(&self, name: &str) -> Option<String> {
+        std::fs::read_to_string(self.fixtures.join(name)).ok()
+    }
+
+    fn not_found(message: &str) -> (u16, String) {
+        let body = serde_json::json!({
+            "error": "Not Found",
+            "status": 404,
+            "message": message,
+        });
+        (404, body.to_string())
+    }
+
+    fn route(&self, request: &Request) -> (u16, String) {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/oauth2/token") => match self.fixture("token.json") {
+                Some(body) => (200, body),
+                None => Self::not_found("token.json fixture is missing"),
+            },
+            ("GET", "/helix/streams") => {
+                // one fixture per live channel, channels without one are offline
+                let mut data = Vec::new();
+                for login in request.query.get("user_login").into_iter().flatten() {
+                    let Some(body) =
+                        self.fixture(&format!("streams/{}.json", login.to_lowercase()))
+                    else {
+                        continue;
+                    };
+                    match serde_json::from_str::<serde_json::Value>(&body) {
+                        Ok(mut page) => {
+                            if let Some(streams) = page["data"].as_array_mut() {
+                                data.append(streams);
+                            }
+                        }
+                        Err(_) => return Self::not_found("streams fixture is malformed"),
+                    }
+                }
+                (
+                    200,
+                    serde_json::json!({ "data": data, "pagination": {} }).to_string(),
+                )
+            }
+            ("GET", "/helix/users") => {
+                let Some(body) = self.fixture("users.json") else {
+                    return Self::not_found("users.json fixture is missing");
+                };
+                let users: serde_json::Value = match serde_json::from_str(&body) {
+                    Ok(users) => users,
+                    Err(_) => return Self::not_found("users.json fixture is malformed"),
+                };
+                let requested = |key: &str, value: &serde_json::Value| {
+                    let value = value.as_str().unwrap_or_default().to_lowercase();
+                    request
+                        .query
+                        .get(key)
+                        .is_some_and(|values| values.iter().any(|v| v.to_lowercase() == value))
+                };
+                let data: Vec<&serde_json::Value> = users["data"]
+                    .as_array()
+                    .map(|users| {
+                        users
+                            .iter()
+                            .filter(|u| {
+                                requested("login", &u["login"]) || requested("id", &u["id"])
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (200, serde_json::json!({ "data": data }).to_string())
+            }
+            _ => Self::not_found(&format!(
+                "{} {} is not mocked",
+                request.method, request.path
+            )),
+        }
+    }
+
+    /// Decodes `%XX` escapes and `+` of a query string component.
+    fn percent_decode(value: &str) -> String {
+        let bytes = value.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let escaped = bytes
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match (bytes[i], escaped) {
+                (b'%', Some(byte)) => {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                (b'+', _) => decoded.push(b' '),
+                (byte, _) => decoded.push(byte),
+            }
+            i += 1;
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+
+    fn parse_query(query: &str) -> HashMap<String, Vec<String>> {
+        let mut parsed: HashMap<String, Vec<String>> = HashMap::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            parsed
+                .entry(Self::percent_decode(key))
+                .or_default()
+                .push(Self::percent_decode(value));
+        }
+        parsed
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> Result<Request, Box<dyn Error>> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().ok_or("Missing method")?.to_string();
+        let target = parts.next().ok_or("Missing request target")?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse()?;
+            }
+        }
+        // the body (form data of the token request) is not used by any route
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await?;
+
+        Ok(Request {
+            method,
+            path: path.to_string(),
+            query: Self::parse_query(query),
+        })
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        let request = Self::read_request(&mut stream).await?;
+        let (status, body) = self.route(&request);
+        info!("{} {} -> {}", request.method, request.path, status);
+        let reason = if status == 200 { "OK" } else { "Not Found" };
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nRatelimit-Limit: 800\r\nRatelimit-Remaining: 799\r\nConnection: close\r\n\r\n{}",
+            status,
+            reason,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_is_percent_decoded() {
+        let query = MockTwitch::parse_query("login=some%20one&login=caf%C3%A9+bar&id=42&empty");
+        assert_eq!(query["login"], vec!["some one", "café bar"]);
+        assert_eq!(query["id"], vec!["42"]);
+        assert_eq!(query["empty"], vec![""]);
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(MockTwitch::percent_decode("100%"), "100%");
+        assert_eq!(MockTwitch::percent_decode("%zz%4"), "%zz%4");
+    }
+}
@@ -1,27 +1,52 @@
 use reqwest::Client;
+pub mod channel_config;
+pub mod chat_archive;
 pub mod chat_message;
-use crate::twitch::chat_message::ChatMessage;
+pub mod commands;
+pub mod helix;
+#[cfg(any(test, feature = "mock-twitch"))]
+pub mod mock;
+pub mod stream_session;
+pub mod users;
+use crate::claims::ClaimJob;
+use crate::claims::rewards::{RewardEvent, Rewards};
+use crate::twitch::channel_config::ChannelsConfig;
+use crate::twitch::chat_archive::ChatArchive;
+use crate::twitch::chat_message::{ChatMessage, Moderation};
+use crate::twitch::commands::{CommandRegistry, CommandServices, Dispatch, Roles};
+use crate::twitch::helix::{HELIX_URL, HelixClient, HelixError, HelixPage};
+use deadpool_postgres::Pool;
+use futures::{FutureExt, TryStreamExt};
 use serde::Deserialize;
 use std::env;
 use std::env::VarError;
 use std::error::Error;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
 use twitch_irc::login::StaticLoginCredentials;
-use twitch_irc::message::ServerMessage::Privmsg;
+use twitch_irc::message::ServerMessage::{ClearChat, ClearMsg, Privmsg, UserNotice};
+use twitch_irc::message::{
+    ClearChatAction, ClearChatMessage, ClearMsgMessage, PrivmsgMessage, UserNoticeEvent,
+    UserNoticeMessage,
+};
 use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
 
+const AUTH_URL: &str = "https://id.twitch.tv";
+/// Helix accepts up to 100 `user_login` parameters per request.
+const MAX_LOGINS_PER_REQUEST: usize = 100;
+
 pub struct TwitchApi {
     secret: String,
     client: String,
-    access_token: Option<String>,
+    auth_url: String,
+    http: Client,
+    helix: HelixClient,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct TwitchStreamResponse {
-    pub data: Vec<StreamInfo>,
-    pub pagination: Option<Pagination>,
-}
+pub type TwitchStreamResponse = HelixPage<StreamInfo>;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct StreamInfo {
@@ -51,28 +76,38 @@ pub struct Pagination {
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
     access_token: String,
-    expires_in: i32,
-    token_type: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct OAuthTokenResponse {
-    access_token: String,
-    refresh_token: String,
-    expires_in: u32,
-    scope: Vec<String>,
-    token_type: String,
 }
 
 impl TwitchApi {
+    /// Reads the credentials from env. `TWITCH_AUTH_URL` and `TWITCH_API_URL`
+    /// override the Twitch hosts, e.g. to run against the `mock-twitch` server.
     pub fn new() -> Result<Self, VarError> {
         let secret = env::var("TWITCH_SECRET")?;
         let client = env::var("TWITCH_CLIENT")?;
-        Ok(Self {
+        let auth_url = env::var("TWITCH_AUTH_URL").unwrap_or(AUTH_URL.to_string());
+        let api_url = env::var("TWITCH_API_URL").unwrap_or(HELIX_URL.to_string());
+        Ok(Self::with_base_urls(secret, client, auth_url, api_url))
+    }
+
+    pub fn with_base_urls(
+        secret: String,
+        client: String,
+        auth_url: String,
+        api_url: String,
+    ) -> Self {
+        let http = Client::new();
+        let helix = HelixClient::new(http.clone(), client.clone(), api_url);
+        Self {
             secret,
             client,
-            access_token: None,
-        })
+            auth_url: auth_url.trim_end_matches('/').to_string(),
+            http,
+            helix,
+        }
+    }
+
+    pub fn helix(&self) -> &HelixClient {
+        &self.helix
     }
 
     pub async fn get_and_store_token(&mut self) -> Result<(), reqwest::Error> {
@@ -81,16 +116,16 @@ impl TwitchApi {
             ("client_secret", &self.secret),
             ("grant_type", &"client_credentials".to_string()),
         ];
-        let client = Client::new();
-        match client
-            .post("https://id.twitch.tv/oauth2/token")
+        match self
+            .http
+            .post(format!("{}/oauth2/token", self.auth_url))
             .form(&form_data)
             .send()
             .await
         {
             Ok(response) => {
-                let parsed_response = response.json::<TokenResponse>().await?;
-                self.access_token = Some(parsed_response.access_token);
+                let parsed_response = response.error_for_status()?.json::<TokenResponse>().await?;
+                self.helix.set_access_token(parsed_response.access_token);
                 Ok(())
             }
             Err(error) => Err(error),
@@ -100,73 +135,312 @@ impl TwitchApi {
     pub async fn get_stream_info(
         &self,
         user_login: &str,
-    ) -> Result<Option<StreamInfo>, reqwest::Error> {
-        let url = format!(
-            "https://api.twitch.tv/helix/streams?user_login={}",
-            user_login
+    ) -> Result<Option<StreamInfo>, HelixError> {
+        let parsed: TwitchStreamResponse = self
+            .helix
+            .get("streams", &[("user_login", user_login.to_string())])
+            .await?;
+        Ok(parsed.data.into_iter().next())
+    }
+
+    /// Returns the live streams among `user_logins`, offline channels are left out.
+    pub async fn get_streams(&self, user_logins: &[&str]) -> Result<Vec<StreamInfo>, HelixError> {
+        let mut streams = Vec::new();
+        for chunk in user_logins.chunks(MAX_LOGINS_PER_REQUEST) {
+            let query = chunk
+                .iter()
+                .map(|login| ("user_login", login.to_string()))
+                .collect();
+            let mut page: Vec<StreamInfo> =
+                self.helix.paginate("streams", query).try_collect().await?;
+            streams.append(&mut page);
+        }
+        Ok(streams)
+    }
+
+    /// Handles a single chat line, returns whether it was a command.
+    async fn handle_privmsg(
+        services: &CommandServices,
+        channels: &ChannelsConfig,
+        registry: &CommandRegistry,
+        priv_msg: PrivmsgMessage,
+    ) -> Result<Dispatch, Box<dyn Error + Send + Sync>> {
+        let Some(channel) = channels.get(&priv_msg.channel_login) else {
+            return Ok(Dispatch::Ignored);
+        };
+        if let Some(bits) = priv_msg.bits {
+            // a failed reward must not swallow a command in the same message
+            let rewarded = async {
+                let client = services.pool.get().await?;
+                Rewards::grant(
+                    &client,
+                    channel,
+                    priv_msg.channel_id.parse()?,
+                    priv_msg.sender.id.parse()?,
+                    RewardEvent::Cheer,
+                    bits,
+                )
+                .await
+            };
+            if let Err(e) = rewarded.await {
+                error!("Failed to reward cheer {}: {:?}", priv_msg.message_id, e);
+            }
+        }
+        let Some(chat_message) = ChatMessage::new(
+            &channel.prefix,
+            &priv_msg.message_text,
+            priv_msg.sender.id.parse()?,
+            priv_msg.channel_id.parse()?,
+            &priv_msg.message_id,
+        ) else {
+            return Ok(Dispatch::Ignored);
+        };
+        let roles = Roles::from_badges(&priv_msg.badges, priv_msg.sender.id == priv_msg.channel_id);
+        registry
+            .dispatch(services, channel, &chat_message, &roles)
+            .await
+    }
+
+    /// Rewards subs, resubs, gifted subs and raids.
+    async fn handle_user_notice(
+        pool: &Pool,
+        channels: &ChannelsConfig,
+        notice: &UserNoticeMessage,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(channel) = channels.get(&notice.channel_login) else {
+            return Ok(());
+        };
+        let (event, amount) = match &notice.event {
+            UserNoticeEvent::SubOrResub {
+                is_resub: false,
+                cumulative_months,
+                ..
+            } => (RewardEvent::Sub, *cumulative_months),
+            UserNoticeEvent::SubOrResub {
+                is_resub: true,
+                cumulative_months,
+                ..
+            } => (RewardEvent::Resub, *cumulative_months),
+            // anonymous gifts are sent by a placeholder user that cannot hold a wallet
+            UserNoticeEvent::SubGift {
+                is_sender_anonymous: false,
+                num_gifted_months,
+                ..
+            } => (RewardEvent::Subgift, *num_gifted_months),
+            UserNoticeEvent::Raid { viewer_count, .. } => (RewardEvent::Raid, *viewer_count),
+            _ => return Ok(()),
+        };
+        let client = pool.get().await?;
+        Rewards::grant(
+            &client,
+            channel,
+            notice.channel_id.parse()?,
+            notice.sender.id.parse()?,
+            event,
+            amount,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// A moderator deleted a single message.
+    async fn handle_clear_msg(
+        pool: &Pool,
+        clear_msg: &ClearMsgMessage,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let client = pool.get().await?;
+        let moderated = Moderation::moderate_message(&client, &clear_msg.message_id).await?;
+        let cancelled = ClaimJob::cancel_for_messages(&client, &moderated).await?;
+        if !moderated.is_empty() {
+            info!(
+                "Message {} of {} deleted in {}, cancelled {} claim(s)",
+                clear_msg.message_id, clear_msg.sender_login, clear_msg.channel_login, cancelled
+            );
+        }
+        Ok(())
+    }
+
+    /// A moderator banned or timed out a user, or cleared the whole chat.
+    async fn handle_clear_chat(
+        pool: &Pool,
+        clear_chat: &ClearChatMessage,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (user_login, user_id) = match &clear_chat.action {
+            ClearChatAction::UserBanned {
+                user_login,
+                user_id,
+            }
+            | ClearChatAction::UserTimedOut {
+                user_login,
+                user_id,
+                ..
+            } => (user_login, user_id),
+            // a /clear only hides chat for everyone, it does not target anyone's messages
+            ClearChatAction::ChatCleared => {
+                info!("Chat of {} was cleared", clear_chat.channel_login);
+                return Ok(());
+            }
+        };
+        let client = pool.get().await?;
+        let moderated =
+            Moderation::moderate_user(&client, clear_chat.channel_id.parse()?, user_id.parse()?)
+                .await?;
+        let cancelled = ClaimJob::cancel_for_messages(&client, &moderated).await?;
+        info!(
+            "User {} was removed from {}, moderated {} message(s) and cancelled {} claim(s)",
+            user_login,
+            clear_chat.channel_login,
+            moderated.len(),
+            cancelled
         );
-        let client = Client::new();
-        let access_token = self
-            .access_token
-            .as_ref()
-            .expect("Access token must be presented");
-        let bearer_token = format!("Bearer {}", access_token);
-        let request = client
-            .get(&url)
-            .header("Client-ID", &self.client)
-            .header("Authorization", bearer_token);
-        match request.send().await {
-            Ok(response) => {
-                let parsed: TwitchStreamResponse =
-                    serde_json::from_str(&response.text().await?).unwrap();
-                if let Some(item) = parsed.data.first() {
-                    Ok(Some(item.clone()))
-                } else {
-                    Ok(None)
-                }
+        Ok(())
+    }
+
+    /// Joins chat as `TWITCH_BOT_LOGIN` with `TWITCH_BOT_TOKEN` when both are set,
+    /// anonymously otherwise. Anonymous clients cannot reply to commands.
+    fn chat_config() -> (ClientConfig<StaticLoginCredentials>, bool) {
+        match (env::var("TWITCH_BOT_LOGIN"), env::var("TWITCH_BOT_TOKEN")) {
+            (Ok(login), Ok(token)) => {
+                let token = token.trim_start_matches("oauth:").to_string();
+                (
+                    ClientConfig::new_simple(StaticLoginCredentials::new(login, Some(token))),
+                    true,
+                )
             }
-            Err(error) => Err(error),
+            _ => (ClientConfig::default(), false),
         }
     }
 
-    pub async fn listen_to_chat() -> Result<(), Box<dyn Error>> {
-        // default configuration is to join chat as anonymous.
-        let config = ClientConfig::default();
+    pub async fn listen_to_chat(
+        channels: Arc<ChannelsConfig>,
+        registry: Arc<CommandRegistry>,
+        services: CommandServices,
+    ) -> Result<(), Box<dyn Error>> {
+        let (config, can_reply) = Self::chat_config();
+        if !can_reply {
+            warn!("TWITCH_BOT_LOGIN or TWITCH_BOT_TOKEN not set, joining chat anonymously");
+        }
         let (mut incoming_messages, client) =
             TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(config);
 
         // first thing you should do: start consuming incoming messages,
         // otherwise they will back up.
-        let join_handle: JoinHandle<Result<(), Box<dyn Error + Send + Sync>>> =
-            tokio::spawn(async move {
-                while let Some(message) = incoming_messages.recv().await {
-                    if let Privmsg(priv_msg) = message {
-                        let msg_id_tag = priv_msg.source.tags.0.get("msg-id");
-                        match msg_id_tag {
-                            Some(_) => {
-                                let chat_message = ChatMessage::new(
-                                    priv_msg.message_text,
-                                    priv_msg.sender.id.parse()?,
-                                );
-                                chat_message.verify_and_send().await.unwrap();
+        let handler_channels = channels.clone();
+        let reply_client = client.clone();
+        let join_handle: JoinHandle<()> = tokio::spawn(async move {
+            let mut stats = ListenerStats::default();
+            let mut archive = ChatArchive::new();
+            while let Some(message) = incoming_messages.recv().await {
+                let priv_msg = match message {
+                    Privmsg(priv_msg) => priv_msg,
+                    UserNotice(notice) => {
+                        if let Err(e) =
+                            Self::handle_user_notice(&services.pool, &handler_channels, &notice)
+                                .await
+                        {
+                            error!("Failed to reward notice {}: {:?}", notice.message_id, e);
+                        }
+                        continue;
+                    }
+                    ClearMsg(clear_msg) => {
+                        if let Err(e) = Self::handle_clear_msg(&services.pool, &clear_msg).await {
+                            error!(
+                                "Failed to moderate message {}: {:?}",
+                                clear_msg.message_id, e
+                            );
+                        }
+                        continue;
+                    }
+                    ClearChat(clear_chat) => {
+                        if let Err(e) = Self::handle_clear_chat(&services.pool, &clear_chat).await {
+                            error!(
+                                "Failed to moderate chat of {}: {:?}",
+                                clear_chat.channel_login, e
+                            );
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let message_id = priv_msg.message_id.clone();
+                let channel_login = priv_msg.channel_login.clone();
+                if handler_channels
+                    .get(&channel_login)
+                    .is_some_and(|channel| channel.archive_chat)
+                {
+                    let archived = async {
+                        let client = services.pool.get().await?;
+                        archive.archive(&client, &priv_msg).await
+                    };
+                    if let Err(e) = archived.await {
+                        error!("Failed to archive message {}: {:?}", message_id, e);
+                    }
+                }
+                // a single message must never take the listener down, not even by panicking
+                let outcome = AssertUnwindSafe(Self::handle_privmsg(
+                    &services,
+                    &handler_channels,
+                    &registry,
+                    priv_msg,
+                ))
+                .catch_unwind()
+                .await;
+                match outcome {
+                    Ok(Ok(Dispatch::Handled(reply))) => {
+                        stats.handled += 1;
+                        if let Some(reply) = reply {
+                            if !can_reply {
+                                info!("Reply to {}: {}", channel_login, reply);
+                            } else if let Err(e) = reply_client.say(channel_login, reply).await {
+                                error!("Failed to reply to message {}: {:?}", message_id, e);
                             }
-                            None => (),
                         }
                     }
+                    Ok(Ok(Dispatch::Ignored)) => stats.ignored += 1,
+                    Ok(Err(e)) => {
+                        stats.failed += 1;
+                        error!(
+                            "Failed to handle message {}: {:?} ({})",
+                            message_id, e, stats
+                        );
+                    }
+                    Err(_) => {
+                        stats.failed += 1;
+                        error!("Handler panicked on message {} ({})", message_id, stats);
+                    }
                 }
-                Ok(())
-            });
+            }
+            info!("Chat connection closed ({})", stats);
+        });
 
-        // join a channel
-        // This function only returns an error if the passed channel login name is malformed,
-        // so in this simple case where the channel name is hardcoded we can ignore the potential
-        // error with `unwrap`.
-        let streamer_channel = env::var("STREAMER").expect("STREAMER env is not set");
-        client.join(streamer_channel.to_owned()).unwrap();
+        // join every configured channel
+        // This function only returns an error if the passed channel login name is malformed.
+        for login in channels.logins() {
+            client.join(login.to_owned())?;
+            info!("Joined channel {}", login);
+        }
 
         // keep the tokio executor alive.
         // If you return instead of waiting the background task will exit.
-        join_handle.await?.expect("Error in join handle");
+        join_handle.await?;
         Ok(())
     }
 }
+
+/// Outcome counters of the chat listener.
+#[derive(Debug, Default)]
+struct ListenerStats {
+    handled: u64,
+    ignored: u64,
+    failed: u64,
+}
+
+impl Display for ListenerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} handled, {} ignored, {} failed",
+            self.handled, self.ignored, self.failed
+        )
+    }
+}
@@ -0,0 +1,119 @@
+use crate::GlobalError;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use crate::twitch::{StreamInfo, TwitchApi};
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const DEFAULT_SAMPLE_INTERVAL_SECS: u64 = 60;
+
+/// A single Twitch broadcast, keyed by the Helix stream ID.
+/// Chat messages and claims point at the session they happened in.
+pub struct StreamSession;
+
+impl StreamSession {
+    /// Inserts the session for `info` if it is not known yet and returns its id.
+    pub async fn upsert(client: &PgClient, info: &StreamInfo) -> Result<Uuid, GlobalError> {
+        let broadcaster_id: i64 = info.user_id.parse()?;
+        let query = "INSERT INTO stream_sessions (stream_id, broadcaster_id, broadcaster_login, started_at) \
+             VALUES ($1, $2, $3, ($4::text)::timestamptz AT TIME ZONE 'UTC') \
+             ON CONFLICT (stream_id) DO UPDATE SET ended_at = NULL \
+             RETURNING id";
+        let row = client
+            .query_one(
+                query,
+                &[
+                    &info.id,
+                    &broadcaster_id,
+                    &info.user_login,
+                    &info.started_at,
+                ],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Marks every open session of the broadcaster, except `current`, as ended.
+    pub async fn close_open(
+        client: &PgClient,
+        broadcaster_login: &str,
+        current: Option<&Uuid>,
+    ) -> Result<u64, GlobalError> {
+        let query = "UPDATE stream_sessions SET ended_at = current_timestamp \
+             WHERE broadcaster_login = $1 AND ended_at IS NULL AND ($2::uuid IS NULL OR id <> $2)";
+        let closed = client
+            .execute(query, &[&broadcaster_login, &current])
+            .await?;
+        Ok(closed)
+    }
+
+    /// Stores the viewer count, game and title of the session at this moment.
+    pub async fn sample(
+        client: &PgClient,
+        session_id: &Uuid,
+        info: &StreamInfo,
+    ) -> Result<(), GlobalError> {
+        let viewer_count = info.viewer_count as i32;
+        let query = "INSERT INTO stream_samples (session_id, viewer_count, game_id, game_name, title) \
+             VALUES ($1, $2, $3, $4, $5)";
+        client
+            .execute(
+                query,
+                &[
+                    session_id,
+                    &viewer_count,
+                    &info.game_id,
+                    &info.game_name,
+                    &info.title,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn track_once(
+        pool: &Pool,
+        twitch_client: &TwitchApi,
+        streamer: &str,
+    ) -> Result<(), GlobalError> {
+        let stream_info = twitch_client.get_stream_info(streamer).await?;
+        let client = pool.get().await?;
+        match stream_info {
+            Some(info) => {
+                let session_id = Self::upsert(&client, &info).await?;
+                Self::close_open(&client, &info.user_login, Some(&session_id)).await?;
+                Self::sample(&client, &session_id, &info).await?;
+                info!(
+                    "Sampled session {} of {}: {} viewers",
+                    session_id, streamer, info.viewer_count
+                );
+            }
+            None => {
+                let closed = Self::close_open(&client, streamer, None).await?;
+                if closed > 0 {
+                    info!(
+                        "Streamer {} went offline, closed {} session(s)",
+                        streamer, closed
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Polls the stream of `streamer` forever, keeping `stream_sessions` and
+    /// `stream_samples` up to date. Failures are logged and retried on the next tick.
+    pub async fn track(pool: Pool, twitch_client: Arc<TwitchApi>, streamer: String) {
+        let interval_secs = env_or("STREAM_SAMPLE_INTERVAL_SECS", DEFAULT_SAMPLE_INTERVAL_SECS);
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = Self::track_once(&pool, &twitch_client, &streamer).await {
+                error!("Failed to sample stream of {}: {:?}", streamer, e);
+            }
+        }
+    }
+}
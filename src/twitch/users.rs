@@ -0,0 +1,124 @@
+use crate::GlobalError;
+use crate::config::env_or;
+use crate::pg::PgClient;
+use crate::twitch::TwitchApi;
+use crate::twitch::helix::HelixPage;
+use serde::Deserialize;
+use tracing::info;
+
+const DEFAULT_USER_CACHE_TTL_SECS: i64 = 24 * 60 * 60;
+/// Helix accepts up to 100 `id` and `login` parameters combined.
+const MAX_USERS_PER_REQUEST: usize = 100;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwitchUser {
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+}
+
+impl TwitchUser {
+    pub fn user_id(&self) -> Result<i64, GlobalError> {
+        Ok(self.id.parse()?)
+    }
+}
+
+fn user_cache_ttl_secs() -> i64 {
+    env_or("USER_CACHE_TTL_SECS", DEFAULT_USER_CACHE_TTL_SECS)
+}
+
+impl TwitchApi {
+    async fn cached_users(
+        client: &PgClient,
+        logins: &[String],
+        ids: &[i64],
+    ) -> Result<Vec<TwitchUser>, GlobalError> {
+        let query = "SELECT user_id, login, display_name FROM viewers \
+             WHERE (login = ANY($1) OR user_id = ANY($2)) \
+             AND fetched_at > current_timestamp - ($3::bigint * interval '1 second')";
+        let rows = client
+            .query(query, &[&logins, &ids, &user_cache_ttl_secs()])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| TwitchUser {
+                id: row.get::<_, i64>(0).to_string(),
+                login: row.get(1),
+                display_name: row.get(2),
+            })
+            .collect())
+    }
+
+    async fn cache_user(client: &PgClient, user: &TwitchUser) -> Result<(), GlobalError> {
+        let user_id = user.user_id()?;
+        // logins can be renamed and taken over by another account
+        client
+            .execute(
+                "DELETE FROM viewers WHERE login = $1 AND user_id <> $2",
+                &[&user.login, &user_id],
+            )
+            .await?;
+        let query = "INSERT INTO viewers (user_id, login, display_name, fetched_at) \
+             VALUES ($1, $2, $3, current_timestamp) \
+             ON CONFLICT (user_id) DO UPDATE \
+             SET login = excluded.login, display_name = excluded.display_name, fetched_at = excluded.fetched_at";
+        client
+            .execute(query, &[&user_id, &user.login, &user.display_name])
+            .await?;
+        Ok(())
+    }
+
+    /// Looks up users by login and/or numeric ID. Fresh entries of the `viewers`
+    /// table are used as is, only the rest is requested from Helix and cached.
+    pub async fn get_users(
+        &self,
+        client: &PgClient,
+        logins: &[&str],
+        ids: &[i64],
+    ) -> Result<Vec<TwitchUser>, GlobalError> {
+        let logins: Vec<String> = logins
+            .iter()
+            .map(|login| login.trim_start_matches('@').to_lowercase())
+            .collect();
+        let mut users = Self::cached_users(client, &logins, ids).await?;
+
+        let mut query: Vec<(&str, String)> = Vec::new();
+        for login in &logins {
+            if !users.iter().any(|u| &u.login == login) {
+                query.push(("login", login.clone()));
+            }
+        }
+        for id in ids {
+            if !users.iter().any(|u| u.id == id.to_string()) {
+                query.push(("id", id.to_string()));
+            }
+        }
+        if query.is_empty() {
+            return Ok(users);
+        }
+
+        info!("Resolving {} user(s) through Helix", query.len());
+        for chunk in query.chunks(MAX_USERS_PER_REQUEST) {
+            let page: HelixPage<TwitchUser> = self.helix().get("users", chunk).await?;
+            for user in page.data {
+                Self::cache_user(client, &user).await?;
+                if !users.iter().any(|u| u.id == user.id) {
+                    users.push(user);
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    pub async fn resolve_login(
+        &self,
+        client: &PgClient,
+        login: &str,
+    ) -> Result<Option<TwitchUser>, GlobalError> {
+        Ok(self
+            .get_users(client, &[login], &[])
+            .await?
+            .into_iter()
+            .next())
+    }
+}
@@ -0,0 +1,251 @@
+//! Drives the Twitch client and the chat commands against the offline mock Twitch server.
+//! The command tests need a database and are skipped when `PG_*` is not set.
+//!
+//! cargo test --features mock-twitch --test twitch_mock
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use twitch_sui_oracle::pg::PgConnect;
+use twitch_sui_oracle::twitch::TwitchApi;
+use twitch_sui_oracle::twitch::channel_config::ChannelConfig;
+use twitch_sui_oracle::twitch::chat_message::ChatMessage;
+use twitch_sui_oracle::twitch::commands::{
+    CommandRegistry, CommandServices, Dispatch, Role, Roles,
+};
+use twitch_sui_oracle::twitch::mock::MockTwitch;
+
+const WALLET: &str = "0x0b35f8ac8a2ef0ecd20e3ac5f3ba5ea4fb0f3ba22e8d5b2ac7b0ddc7e5206091";
+/// `andiskey` and `twitchdev` of `fixtures/twitch/users.json`.
+const ANDISKEY: i64 = 679549793;
+const TWITCHDEV: i64 = 141981764;
+
+async fn start_mock() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mock = Arc::new(MockTwitch::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/twitch"
+    )));
+    tokio::spawn(mock.serve(listener));
+    format!("http://{}", addr)
+}
+
+async fn twitch_api() -> TwitchApi {
+    let url = start_mock().await;
+    let mut twitch = TwitchApi::with_base_urls(
+        "secret".to_string(),
+        "client".to_string(),
+        url.clone(),
+        format!("{}/helix", url),
+    );
+    twitch.get_and_store_token().await.unwrap();
+    twitch
+}
+
+async fn test_pool() -> Option<Pool> {
+    let Ok(pool) = PgConnect::create_pool_from_env() else {
+        eprintln!("PG_* is not set, skipping");
+        return None;
+    };
+    let client = pool.get().await.unwrap();
+    PgConnect::run_migrations(&client).await.unwrap();
+    Some(pool)
+}
+
+/// A channel ID no other test run used, so queued claims and cooldowns don't leak between runs.
+fn fresh_channel_id() -> i64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    (nanos % 1_000_000_000_000) as i64
+}
+
+struct Chat {
+    registry: CommandRegistry,
+    services: CommandServices,
+    channel: ChannelConfig,
+    channel_id: i64,
+    /// Messages sent so far, numbers the IRC message IDs.
+    sent: AtomicU64,
+}
+
+impl Chat {
+    async fn send(&self, user_id: i64, roles: &[Role], line: &str) -> Dispatch {
+        let message = ChatMessage::new(
+            &self.channel.prefix,
+            line,
+            user_id,
+            self.channel_id,
+            &format!(
+                "{}-{}",
+                self.channel_id,
+                self.sent.fetch_add(1, Ordering::Relaxed)
+            ),
+        )
+        .expect("Line is not a command");
+        self.registry
+            .dispatch(
+                &self.services,
+                &self.channel,
+                &message,
+                &Roles::new(roles.to_vec()),
+            )
+            .await
+            .unwrap()
+    }
+}
+
+async fn chat(channel: ChannelConfig) -> Option<Chat> {
+    let pool = test_pool().await?;
+    Some(Chat {
+        registry: CommandRegistry::with_defaults(),
+        services: CommandServices {
+            pool,
+            twitch: Arc::new(twitch_api().await),
+            oracle: None,
+        },
+        channel,
+        channel_id: fresh_channel_id(),
+        sent: AtomicU64::new(0),
+    })
+}
+
+fn reply(text: &str) -> Dispatch {
+    Dispatch::Handled(Some(text.to_string()))
+}
+
+#[tokio::test]
+async fn streams_are_read_from_fixtures() {
+    let twitch = twitch_api().await;
+    let streams = twitch
+        .get_streams(&["AndISkey", "twitchdev"])
+        .await
+        .unwrap();
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].user_login, "andiskey");
+
+    let info = twitch.get_stream_info("andiskey").await.unwrap().unwrap();
+    assert_eq!(info.user_id, ANDISKEY.to_string());
+    assert!(twitch.get_stream_info("twitchdev").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn users_are_resolved_through_the_mock() {
+    let Some(pool) = test_pool().await else {
+        return;
+    };
+    let client = pool.get().await.unwrap();
+    let twitch = twitch_api().await;
+    let user = twitch
+        .resolve_login(&client, "@TwitchDev")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.user_id().unwrap(), TWITCHDEV);
+    assert_eq!(user.display_name, "TwitchDev");
+    assert!(
+        twitch
+            .resolve_login(&client, "nobody")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn claims_are_queued_and_revoked() {
+    let Some(chat) = chat(ChannelConfig::new("andiskey".to_string())).await else {
+        return;
+    };
+    let status = |queued: usize| {
+        reply(&format!(
+            "Minting running | {} claim(s) queued | oracle balance no wallet",
+            queued
+        ))
+    };
+
+    // a user without a wallet, the channel ID is just as unique
+    assert_eq!(
+        chat.send(chat.channel_id, &[], "!nft").await,
+        reply("Send your Sui address first: !NFT <address>")
+    );
+    assert_eq!(
+        chat.send(ANDISKEY, &[], &format!("!NFT {}", WALLET)).await,
+        Dispatch::Handled(None)
+    );
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!NFT").await,
+        Dispatch::Handled(None)
+    );
+    assert_eq!(
+        chat.send(TWITCHDEV, &[Role::Moderator], "!STATUS").await,
+        status(2)
+    );
+
+    // moderator commands are ignored for viewers
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!REVOKE @andiskey").await,
+        Dispatch::Ignored
+    );
+    assert_eq!(
+        chat.send(TWITCHDEV, &[Role::Moderator], "!REVOKE @AndISkey")
+            .await,
+        reply("Revoked 2 pending claim(s) of @Andiskey")
+    );
+    assert_eq!(
+        chat.send(TWITCHDEV, &[Role::Moderator], "!STATUS").await,
+        status(0)
+    );
+    assert_eq!(
+        chat.send(TWITCHDEV, &[Role::Moderator], "!AIRDROP @nobody")
+            .await,
+        reply("Unknown user @nobody")
+    );
+}
+
+#[tokio::test]
+async fn wrong_usage_and_cooldowns_are_ignored() {
+    let mut channel = ChannelConfig::new("andiskey".to_string());
+    channel.cooldowns.per_user_secs = 60;
+    let Some(chat) = chat(channel).await else {
+        return;
+    };
+
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!NFT not-an-address").await,
+        Dispatch::Ignored
+    );
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!UNKNOWN").await,
+        Dispatch::Ignored
+    );
+    assert_eq!(chat.send(ANDISKEY, &[], "!STORE").await, Dispatch::Ignored);
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!STORE hello").await,
+        Dispatch::Handled(None)
+    );
+    assert_eq!(
+        chat.send(ANDISKEY, &[], "!STORE again").await,
+        Dispatch::Ignored
+    );
+    // the cooldown is per user
+    assert_eq!(
+        chat.send(TWITCHDEV, &[], "!STORE hello").await,
+        Dispatch::Handled(None)
+    );
+
+    // a rejected command does not start the cooldown
+    let user_id = chat.channel_id;
+    assert_eq!(
+        chat.send(user_id, &[], "!NFT").await,
+        reply("Send your Sui address first: !NFT <address>")
+    );
+    assert_eq!(
+        chat.send(user_id, &[], &format!("!NFT {}", WALLET)).await,
+        Dispatch::Handled(None)
+    );
+    assert_eq!(chat.send(user_id, &[], "!NFT").await, Dispatch::Ignored);
+}